@@ -1,12 +1,24 @@
 mod number;
+pub mod precedence;
 
-pub use self::number::Number;
+pub use self::number::{Number, DEFAULT_PRECISION};
+
+use std::fmt::{self, Display, Formatter};
 
 use lalrpop_util::lalrpop_mod;
 
+use crate::token::Span;
+
 /// Custom parsing errors
 #[derive(Debug, PartialEq)]
-pub enum Error {}
+pub enum Error {
+    /// An operator that is only valid between two operands was used as a prefix
+    InvalidPrefixOperator {
+        op: String,
+        start: usize,
+        end: usize,
+    },
+}
 
 pub type TErrorRecovery<'input> =
     lalrpop_util::ErrorRecovery<usize, crate::token::Token<'input>, Error>;
@@ -17,6 +29,48 @@ lalrpop_mod!(
     grammar
 );
 
+/// A node, or part of one, along with where it is in the source
+#[derive(Debug, Clone)]
+pub struct Spanned<T> {
+    pub node: T,
+    pub span: Span,
+}
+
+pub type SpannedNode = Spanned<Node>;
+
+impl<T> Spanned<T> {
+    pub fn new(node: T, start: usize, end: usize) -> Spanned<T> {
+        Spanned {
+            node,
+            span: (start, end).into(),
+        }
+    }
+}
+
+/// Spans are ignored, so the same expression is equal wherever it was written
+impl<T: PartialEq> PartialEq for Spanned<T> {
+    fn eq(&self, other: &Spanned<T>) -> bool {
+        self.node == other.node
+    }
+}
+
+impl<T: Display> Display for Spanned<T> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.node.fmt(f)
+    }
+}
+
+impl SpannedNode {
+    /// Finds the innermost node with the given span
+    pub fn find(&self, span: Span) -> Option<&SpannedNode> {
+        self.node
+            .children()
+            .into_iter()
+            .find_map(|child| child.find(span))
+            .or_else(|| if self.span == span { Some(self) } else { None })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq)]
 pub enum Node {
     Number(Number),
@@ -25,47 +79,118 @@ pub enum Node {
     Ident(String),
 
     Infix {
-        lhs: Box<Node>,
+        lhs: Box<SpannedNode>,
+        op: String,
+        rhs: Box<SpannedNode>,
+    },
+    Prefix {
         op: String,
-        rhs: Box<Node>,
+        rhs: Box<SpannedNode>,
     },
     FunctionDef {
         name: String,
         params: Vec<String>,
-        body: Vec<Node>,
+        body: Vec<SpannedNode>,
     },
     FunctionCall {
         name: String,
-        args: Vec<Node>,
+        args: Vec<SpannedNode>,
     },
 
     ShellCall {
         cmd: String,
     },
 
-    Let(String, Box<Node>),
-    Prev,
+    Let(String, Box<SpannedNode>),
+    Prev(Recall),
     Error,
 }
 
+impl Node {
+    /// The nodes directly inside this one
+    pub fn children(&self) -> Vec<&SpannedNode> {
+        match self {
+            Node::Infix { lhs, rhs, .. } => vec![lhs.as_ref(), rhs.as_ref()],
+            Node::Prefix { rhs, .. } | Node::Let(_, rhs) => vec![rhs.as_ref()],
+            Node::FunctionDef { body, .. } => body.iter().collect(),
+            Node::FunctionCall { args, .. } => args.iter().collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// Which previous result a `#` refers to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Recall {
+    /// Counting from the first result, which is 1, written `#3`
+    Absolute(usize),
+    /// Counting back from the latest result, which is 1, written `#`, `##` or `#-2`
+    Relative(usize),
+}
+
+/// Writes a list of nodes separated by `sep`
+fn write_sep(f: &mut Formatter, nodes: &[SpannedNode], sep: &str) -> fmt::Result {
+    for (i, node) in nodes.iter().enumerate() {
+        if i != 0 {
+            f.write_str(sep)?;
+        }
+        node.fmt(f)?;
+    }
+    Ok(())
+}
+
+/// Formats the node as source code that parses back into the same node
+///
+/// Operations are fully parenthesised, so precedence does not need to be considered
+impl Display for Node {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::Node::*;
+        match self {
+            Number(n) => {
+                let needs_parens = match n {
+                    self::Number::Int(_) | self::Number::Float(_) => n.is_negative(),
+                    self::Number::Rational(_) | self::Number::Complex(_) => true,
+                };
+                if needs_parens {
+                    write!(f, "({})", n)
+                } else {
+                    write!(f, "{}", n)
+                }
+            }
+            String(s) => write!(f, "{:?}", s),
+            Ident(name) => f.write_str(name),
+            Infix { lhs, op, rhs } => write!(f, "({} {} {})", lhs, op, rhs),
+            Prefix { op, rhs } => write!(f, "({}{})", op, rhs),
+            FunctionDef { name, params, body } => {
+                write!(f, "fn {}<{}>{{", name, params.join(", "))?;
+                write_sep(f, body, "; ")?;
+                write!(f, "}}")
+            }
+            FunctionCall { name, args } => {
+                write!(f, "{}<", name)?;
+                write_sep(f, args, ", ")?;
+                write!(f, ">")
+            }
+            ShellCall { cmd } => write!(f, "${:?}", cmd),
+            Let(name, value) => write!(f, "let {} = ({})", name, value),
+            Prev(Recall::Absolute(n)) => write!(f, "#{}", n),
+            Prev(Recall::Relative(1)) => write!(f, "#"),
+            Prev(Recall::Relative(n)) => write!(f, "#-{}", n),
+            Error => write!(f, "<error>"),
+        }
+    }
+}
+
 pub fn parse<'input, 'err>(
     errors: &'err mut Vec<TErrorRecovery<'input>>,
     tokens: Vec<Result<(usize, crate::token::Token<'input>, usize), Error>>,
-) -> Result<Vec<Node>, TParseError<'input>> {
-    let ast = grammar::NodesParser::new().parse(errors, tokens.into_iter())?;
-
-    // TODO: Reparse
-
-    Ok(ast)
+) -> Result<Vec<SpannedNode>, TParseError<'input>> {
+    grammar::NodesParser::new().parse(errors, tokens.into_iter())
 }
 
 pub fn parse_single<'input, 'err>(
     errors: &'err mut Vec<TErrorRecovery<'input>>,
     tokens: Vec<Result<(usize, crate::token::Token<'input>, usize), Error>>,
-) -> Result<Node, TParseError<'input>> {
-    let ast = grammar::NodeParser::new().parse(errors, tokens.into_iter())?;
-
-    // TODO: Reparse
-
-    Ok(ast)
+) -> Result<SpannedNode, TParseError<'input>> {
+    grammar::NodeParser::new().parse(errors, tokens.into_iter())
 }
@@ -1,13 +1,20 @@
 use rug::float::Round;
-use rug::{self, Float as RFloat, Integer};
+use rug::ops::Pow;
+use rug::{self, Complex as RComplex, Float as RFloat, Integer, Rational as RRational};
 
 use std::fmt::{Display, Error, Formatter};
-use std::ops::{Add, Div, Mul, Shl, Shr, Sub};
+use std::ops::{Add, Div, Mul, Neg, Not, Rem, Shl, Shr, Sub};
+
+/// Precision, in bits, used for floats when no other precision is known
+pub const DEFAULT_PRECISION: u32 = 53;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum Number {
     Int(Integer),
+    /// Always in canonical form, and never has a denominator of 1
+    Rational(RRational),
     Float(RFloat),
+    Complex(RComplex),
 }
 
 impl Default for Number {
@@ -28,13 +35,78 @@ impl From<Integer> for Number {
     }
 }
 
+impl From<RComplex> for Number {
+    fn from(x: RComplex) -> Number {
+        Number::Complex(x)
+    }
+}
+
+impl From<RRational> for Number {
+    fn from(x: RRational) -> Number {
+        normalize(x)
+    }
+}
+
 impl Display for Number {
     fn fmt(&self, fmt: &mut Formatter) -> Result<(), Error> {
         use self::Number::*;
         match *self {
             Int(ref i) => i.fmt(fmt),
+            Rational(ref r) => r.fmt(fmt),
             Float(ref f) => f.fmt(fmt),
+            Complex(ref c) => {
+                let (re, im) = (c.real(), c.imag());
+                if im.is_sign_negative() {
+                    write!(fmt, "{}-{}i", re, RFloat::with_val(im.prec(), -im))
+                } else {
+                    write!(fmt, "{}+{}i", re, im)
+                }
+            }
+        }
+    }
+}
+
+/// Turns a rational with a denominator of 1 back into an integer
+fn normalize(r: RRational) -> Number {
+    if *r.denom() == 1 {
+        Number::Int(r.into_numer_denom().0)
+    } else {
+        Number::Rational(r)
+    }
+}
+
+/// The precision of a float produced by combining two numbers, exact numbers
+/// on both sides fall back to `default`
+fn common_prec(l: &Number, r: &Number, default: u32) -> u32 {
+    match (l.is_exact(), r.is_exact()) {
+        (true, true) => default,
+        (true, false) => r.prec(),
+        (false, true) => l.prec(),
+        (false, false) => l.prec().max(r.prec()),
+    }
+}
+
+/// Both operands of a binary operation, converted to a common representation
+enum Operands {
+    Int(Integer, Integer),
+    Rational(RRational, RRational),
+    Float(RFloat, RFloat),
+    Complex(RComplex, RComplex),
+}
+
+fn promote(l: Number, r: Number) -> Operands {
+    use self::Number::*;
+    match (l, r) {
+        (Int(l), Int(r)) => Operands::Int(l, r),
+        (l @ Complex(_), r) | (l, r @ Complex(_)) => {
+            let prec = common_prec(&l, &r, DEFAULT_PRECISION);
+            Operands::Complex(l.into_complex(prec), r.into_complex(prec))
+        }
+        (l @ Float(_), r) | (l, r @ Float(_)) => {
+            let prec = common_prec(&l, &r, DEFAULT_PRECISION);
+            Operands::Float(l.into_float(prec), r.into_float(prec))
         }
+        (l, r) => Operands::Rational(l.into_rational(), r.into_rational()),
     }
 }
 
@@ -44,32 +116,48 @@ macro_rules! impl_op {
             type Output = Self;
 
             fn $fn(self, other: Self) -> Self {
-                use self::Number::*;
-                match (self, other) {
-                    (Int(l), Int(r)) => Int(l $sym r),
-                    (Int(l), Float(r)) => {
-                        Number::Float(RFloat::with_val(r.prec(), l $sym r))
-                    },
-                    (Float(l), Int(r)) => {
-                        Number::Float(RFloat::with_val(l.prec(), l $sym r))
-                    },
-                    (Float(l), Float(r)) => {
-                        Number::Float(RFloat::with_val(l.prec().max(r.prec()), l $sym r))
-                    }
+                match promote(self, other) {
+                    Operands::Int(l, r) => Number::Int(l $sym r),
+                    Operands::Rational(l, r) => normalize(l $sym r),
+                    Operands::Float(l, r) => Number::Float(l $sym r),
+                    Operands::Complex(l, r) => Number::Complex(l $sym r),
                 }
             }
         }
     };
 }
 
+/// Generates methods that upgrade to a float, exact numbers use the given precision
 macro_rules! impl_upgrading_method {
     ($($op:ident),*) => {
         $(
-            pub fn $op(self) -> Self {
-                use self::Number::*;
+            pub fn $op(self, prec: u32) -> Self {
                 match self {
-                    Int(v) => Number::Float(RFloat::with_val(53, v).$op()),
-                    Float(v) => Number::Float(v.$op()),
+                    Number::Complex(v) => Number::Complex(v.$op()),
+                    other => {
+                        let prec = other.float_prec(prec);
+                        Number::Float(other.into_float(prec).$op())
+                    }
+                }
+            }
+        )*
+    };
+}
+
+/// Like `impl_upgrading_method`, but complex numbers use the reciprocal of another function
+macro_rules! impl_reciprocal_method {
+    ($($op:ident => $reciprocal:ident),*) => {
+        $(
+            pub fn $op(self, prec: u32) -> Self {
+                match self {
+                    Number::Complex(v) => {
+                        let prec = v.prec();
+                        Number::Complex(RComplex::with_val(prec, 1) / v.$reciprocal())
+                    }
+                    other => {
+                        let prec = other.float_prec(prec);
+                        Number::Float(other.into_float(prec).$op())
+                    }
                 }
             }
         )*
@@ -80,66 +168,72 @@ impl Div for Number {
     type Output = Self;
 
     fn div(self, other: Self) -> Self {
-        use self::Number::*;
-        match (self, other) {
-            // TODO: Automatic precision?
-            (Int(l), Int(r)) => Number::Float(RFloat::with_val(
-                53,
-                RFloat::with_val(53, l) / RFloat::with_val(53, r),
-            )),
-            (Int(l), Float(r)) => Number::Float(RFloat::with_val(r.prec(), l / r)),
-            (Float(l), Int(r)) => Number::Float(RFloat::with_val(l.prec(), l / r)),
-            (Float(l), Float(r)) => Number::Float(RFloat::with_val(l.prec().max(r.prec()), l / r)),
+        match promote(self, other) {
+            Operands::Int(l, r) => normalize(RRational::from((l, r))),
+            Operands::Rational(l, r) => normalize(l / r),
+            Operands::Float(l, r) => Number::Float(l / r),
+            Operands::Complex(l, r) => Number::Complex(l / r),
         }
     }
 }
 
-impl rug::ops::Pow<Number> for Number {
+impl Rem for Number {
     type Output = Self;
 
-    fn pow(self, other: Self) -> Self {
-        use self::Number::*;
-        match (self, other) {
-            // TODO: Correct these, why are there missing implementations?
-            (Int(l), Int(r)) => Number::Int(l.pow(r.to_u32().unwrap_or(std::u32::MAX))),
-            (Int(l), Float(r)) => Number::Float(RFloat::with_val(
-                r.prec(),
-                l.pow(
-                    r.to_u32_saturating_round(Round::Nearest)
-                        .unwrap_or(std::u32::MAX),
-                ),
-            )),
-            (Float(l), Int(r)) => Number::Float(RFloat::with_val(l.prec(), l.pow(r))),
-            (Float(l), Float(r)) => {
-                Number::Float(RFloat::with_val(l.prec().max(r.prec()), l.pow(r)))
+    fn rem(self, other: Self) -> Self {
+        match promote(self, other) {
+            Operands::Int(l, r) => Number::Int(l % r),
+            Operands::Rational(l, r) => {
+                let quotient = (l.clone() / &r).trunc();
+                normalize(l - quotient * r)
+            }
+            Operands::Float(l, r) => Number::Float(l % r),
+            // Not defined for complex numbers, the evaluator refuses these
+            Operands::Complex(l, _) => {
+                let prec = l.prec();
+                Number::Complex(RComplex::with_val(prec, (std::f64::NAN, std::f64::NAN)))
             }
         }
     }
 }
 
+impl Pow<Number> for Number {
+    type Output = Self;
+
+    fn pow(self, other: Self) -> Self {
+        self.pow_with_prec(other, DEFAULT_PRECISION)
+    }
+}
+
+/// Raises to a power using floats, or complex numbers if the result is not real
+fn float_pow(l: Number, r: Number, default_prec: u32) -> Number {
+    let prec = common_prec(&l, &r, default_prec);
+    let is_complex = l.is_complex() || r.is_complex() || (l.is_negative() && !r.is_integer());
+    if is_complex {
+        Number::Complex(l.into_complex(prec).pow(r.into_complex(prec)))
+    } else {
+        Number::Float(l.into_float(prec).pow(r.into_float(prec)))
+    }
+}
+
+/// Converts a shift amount to a u32, rounding and saturating as needed
+fn shift_amount(r: Number) -> u32 {
+    r.into_float(DEFAULT_PRECISION)
+        .to_u32_saturating_round(Round::Nearest)
+        .unwrap_or(std::u32::MAX)
+}
+
 impl Shl<Number> for Number {
     type Output = Self;
 
     fn shl(self, other: Self) -> Self {
         use self::Number::*;
-        match (self, other) {
-            (Int(l), Int(r)) => Int(l << r.to_u32().unwrap_or(std::u32::MAX)),
-            (Int(l), Float(r)) => Number::Float(RFloat::with_val(
-                r.prec(),
-                l << r
-                    .to_u32_saturating_round(Round::Nearest)
-                    .unwrap_or(std::u32::MAX),
-            )),
-            (Float(l), Int(r)) => Number::Float(RFloat::with_val(
-                l.prec(),
-                l << r.to_u32().unwrap_or(std::u32::MAX),
-            )),
-            (Float(l), Float(r)) => Number::Float(RFloat::with_val(
-                l.prec().max(r.prec()),
-                l << r
-                    .to_u32_saturating_round(Round::Nearest)
-                    .unwrap_or(std::u32::MAX),
-            )),
+        let amount = shift_amount(other);
+        match self {
+            Int(l) => Int(l << amount),
+            Rational(l) => normalize(l << amount),
+            Float(l) => Float(l << amount),
+            Complex(l) => Complex(l << amount),
         }
     }
 }
@@ -149,24 +243,44 @@ impl Shr<Number> for Number {
 
     fn shr(self, other: Self) -> Self {
         use self::Number::*;
-        match (self, other) {
-            (Int(l), Int(r)) => Int(l >> r.to_u32().unwrap_or(std::u32::MAX)),
-            (Int(l), Float(r)) => Number::Float(RFloat::with_val(
-                r.prec(),
-                l >> r
-                    .to_u32_saturating_round(Round::Nearest)
-                    .unwrap_or(std::u32::MAX),
-            )),
-            (Float(l), Int(r)) => Number::Float(RFloat::with_val(
-                l.prec(),
-                l >> r.to_u32().unwrap_or(std::u32::MAX),
-            )),
-            (Float(l), Float(r)) => Number::Float(RFloat::with_val(
-                l.prec().max(r.prec()),
-                l >> r
-                    .to_u32_saturating_round(Round::Nearest)
-                    .unwrap_or(std::u32::MAX),
-            )),
+        let amount = shift_amount(other);
+        match self {
+            Int(l) => Int(l >> amount),
+            Rational(l) => normalize(l >> amount),
+            Float(l) => Float(l >> amount),
+            Complex(l) => Complex(l >> amount),
+        }
+    }
+}
+
+impl Neg for Number {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        use self::Number::*;
+        match self {
+            Int(v) => Int(-v),
+            Rational(v) => Rational(-v),
+            Float(v) => Float(-v),
+            Complex(v) => Complex(-v),
+        }
+    }
+}
+
+/// Bitwise not, other numbers are truncated to an integer first
+impl Not for Number {
+    type Output = Self;
+
+    fn not(self) -> Self {
+        use self::Number::*;
+        match self {
+            Int(v) => Int(!v),
+            Rational(v) => Int(!v.trunc().into_numer_denom().0),
+            Float(v) => Int(!v
+                .to_integer_round(Round::Zero)
+                .map(|(i, _)| i)
+                .unwrap_or_default()),
+            Complex(v) => !Float(v.into_real_imag().0),
         }
     }
 }
@@ -176,5 +290,193 @@ impl_op!(Sub, sub, -);
 impl_op!(Mul, mul, *);
 
 impl Number {
-    impl_upgrading_method!(sin, cos, tan, asin, acos, atan, csc, sec, cot);
+    impl_upgrading_method!(sin, cos, tan, asin, acos, atan);
+    impl_reciprocal_method!(csc => sin, sec => cos, cot => tan);
+
+    /// Raises to a power, if both numbers are exact but the result is not
+    /// then `prec` is used for the resulting float
+    pub fn pow_with_prec(self, other: Self, prec: u32) -> Self {
+        use self::Number::*;
+        match (self, other) {
+            (Int(l), Int(r)) => match (r.to_u32(), r.to_i32()) {
+                (Some(r), _) => Int(l.pow(r)),
+                // Negative exponents produce an exact fraction
+                (None, Some(r)) => normalize(RRational::from(l).pow(r)),
+                (None, None) => float_pow(Int(l), Int(r), prec),
+            },
+            (Rational(l), Int(r)) => match r.to_i32() {
+                Some(r) => normalize(l.pow(r)),
+                None => float_pow(Rational(l), Int(r), prec),
+            },
+            (l, r) => float_pow(l, r, prec),
+        }
+    }
+
+    /// Builds a purely imaginary number
+    pub fn imaginary(im: RFloat) -> Number {
+        Number::Complex(RComplex::with_val(im.prec(), (0, im)))
+    }
+
+    /// The square root, which is complex for negative numbers
+    pub fn sqrt(self, prec: u32) -> Self {
+        match self {
+            Number::Complex(v) => Number::Complex(v.sqrt()),
+            n => {
+                let prec = n.float_prec(prec);
+                if n.is_negative() {
+                    Number::Complex(n.into_complex(prec).sqrt())
+                } else {
+                    Number::Float(n.into_float(prec).sqrt())
+                }
+            }
+        }
+    }
+
+    /// The real part
+    pub fn re(self) -> Self {
+        match self {
+            Number::Complex(v) => Number::Float(v.into_real_imag().0),
+            other => other,
+        }
+    }
+
+    /// The imaginary part
+    pub fn im(self) -> Self {
+        match self {
+            Number::Complex(v) => Number::Float(v.into_real_imag().1),
+            _ => Number::default(),
+        }
+    }
+
+    /// The complex conjugate
+    pub fn conj(self) -> Self {
+        match self {
+            Number::Complex(v) => Number::Complex(v.conj()),
+            other => other,
+        }
+    }
+
+    /// The argument (phase angle), in radians
+    pub fn arg(self, prec: u32) -> Self {
+        let prec = self.float_prec(prec);
+        let complex = self.into_complex(prec);
+        Number::Float(RFloat::with_val(prec, complex.arg_ref()))
+    }
+
+    /// The absolute value, or magnitude for complex numbers
+    pub fn abs(self) -> Self {
+        use self::Number::*;
+        match self {
+            Int(v) => Int(v.abs()),
+            Rational(v) => Rational(v.abs()),
+            Float(v) => Float(v.abs()),
+            Complex(v) => {
+                let prec = v.prec().0.max(v.prec().1);
+                Float(RFloat::with_val(prec, v.abs_ref()))
+            }
+        }
+    }
+
+    /// Whether the number is an integer or rational, rather than a float
+    pub fn is_exact(&self) -> bool {
+        matches!(self, Number::Int(_) | Number::Rational(_))
+    }
+
+    pub fn is_complex(&self) -> bool {
+        matches!(self, Number::Complex(_))
+    }
+
+    pub fn is_integer(&self) -> bool {
+        use self::Number::*;
+        match self {
+            Int(_) => true,
+            Rational(_) => false,
+            Float(v) => v.is_integer(),
+            Complex(v) => v.imag().is_zero() && v.real().is_integer(),
+        }
+    }
+
+    /// A human readable name for the kind of number, used in errors
+    pub fn type_name(&self) -> &'static str {
+        if self.is_complex() {
+            "complex number"
+        } else {
+            "number"
+        }
+    }
+
+    pub fn is_zero(&self) -> bool {
+        use self::Number::*;
+        match self {
+            Int(v) => *v == 0,
+            Rational(v) => *v == 0,
+            Float(v) => v.is_zero(),
+            Complex(v) => v.real().is_zero() && v.imag().is_zero(),
+        }
+    }
+
+    /// Whether the number is real and below zero
+    pub fn is_negative(&self) -> bool {
+        use self::Number::*;
+        match self {
+            Int(v) => *v < 0,
+            Rational(v) => *v < 0,
+            Float(v) => *v < 0,
+            Complex(_) => false,
+        }
+    }
+
+    /// The precision of a float, or the default precision for exact numbers
+    pub fn prec(&self) -> u32 {
+        self.float_prec(DEFAULT_PRECISION)
+    }
+
+    /// The precision of a float, or `default` for exact numbers
+    pub fn float_prec(&self, default: u32) -> u32 {
+        match self {
+            Number::Float(v) => v.prec(),
+            Number::Complex(v) => v.prec().0.max(v.prec().1),
+            _ => default,
+        }
+    }
+
+    /// Converts to a float of the given precision, rounding if needed
+    ///
+    /// Complex numbers lose their imaginary part
+    pub fn into_float(self, prec: u32) -> RFloat {
+        use self::Number::*;
+        match self {
+            Int(v) => RFloat::with_val(prec, v),
+            Rational(v) => RFloat::with_val(prec, v),
+            Float(v) => RFloat::with_val(prec, v),
+            Complex(v) => RFloat::with_val(prec, v.into_real_imag().0),
+        }
+    }
+
+    /// Converts to a complex number of the given precision, rounding if needed
+    pub fn into_complex(self, prec: u32) -> RComplex {
+        match self {
+            Number::Complex(v) => RComplex::with_val(prec, v),
+            other => RComplex::with_val(prec, (other.into_float(prec), 0)),
+        }
+    }
+
+    /// Converts an exact number to a rational, floats are converted exactly
+    fn into_rational(self) -> RRational {
+        use self::Number::*;
+        match self {
+            Int(v) => RRational::from(v),
+            Rational(v) => v,
+            Float(v) => v.to_rational().unwrap_or_default(),
+            Complex(v) => v.into_real_imag().0.to_rational().unwrap_or_default(),
+        }
+    }
+
+    /// Converts rationals to floats of the given precision, leaving other numbers untouched
+    pub fn to_decimal(&self, prec: u32) -> Number {
+        match self {
+            Number::Rational(v) => Number::Float(RFloat::with_val(prec, v)),
+            other => other.clone(),
+        }
+    }
 }
@@ -0,0 +1,129 @@
+use super::{Node, Spanned, SpannedNode};
+
+use std::iter::Peekable;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Assoc {
+    Left,
+    Right,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OperatorInfo {
+    pub precedence: u8,
+    pub assoc: Assoc,
+}
+
+/// Binary operators, their precedence (higher binds tighter) and associativity
+pub const OPERATORS: &[(&str, OperatorInfo)] = &[
+    ("<<", op(1, Assoc::Left)),
+    (">>", op(1, Assoc::Left)),
+    ("+", op(2, Assoc::Left)),
+    ("-", op(2, Assoc::Left)),
+    ("*", op(3, Assoc::Left)),
+    ("/", op(3, Assoc::Left)),
+    ("%", op(3, Assoc::Left)),
+    ("**", op(5, Assoc::Right)),
+];
+
+/// Unary prefix operators
+pub const PREFIX_OPERATORS: &[&str] = &["-", "+", "~", "!"];
+
+/// Prefix operators bind tighter than everything but `**`, so `-2**2` is `-(2**2)`
+pub const PREFIX_PRECEDENCE: u8 = 4;
+
+/// Used for operators missing from the table, binds looser than everything else
+const FALLBACK: OperatorInfo = op(0, Assoc::Left);
+
+const fn op(precedence: u8, assoc: Assoc) -> OperatorInfo {
+    OperatorInfo { precedence, assoc }
+}
+
+pub fn operator_info(op: &str) -> OperatorInfo {
+    OPERATORS
+        .iter()
+        .find(|(sym, _)| *sym == op)
+        .map(|(_, info)| *info)
+        .unwrap_or(FALLBACK)
+}
+
+pub fn is_prefix_operator(op: &str) -> bool {
+    PREFIX_OPERATORS.contains(&op)
+}
+
+/// A single operand in an infix chain, along with any prefix operators applied to it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Operand {
+    pub prefix: Vec<Spanned<String>>,
+    pub node: SpannedNode,
+}
+
+/// Builds a tree out of a flat chain of infix operations, respecting the operator table
+///
+/// `rest` holds each operator along with the operand that follows it
+pub fn climb(first: Operand, rest: Vec<(String, Operand)>) -> SpannedNode {
+    let mut rest = rest.into_iter().peekable();
+    let lhs = resolve_operand(first, &mut rest);
+    climb_from(lhs, &mut rest, 0)
+}
+
+fn climb_from<I>(mut lhs: SpannedNode, rest: &mut Peekable<I>, min_precedence: u8) -> SpannedNode
+where
+    I: Iterator<Item = (String, Operand)>,
+{
+    while let Some(current) = next_operator(rest) {
+        if current.precedence < min_precedence {
+            break;
+        }
+        let (op, rhs) = rest.next().unwrap(); // Safe, we just peeked
+        let mut rhs = resolve_operand(rhs, rest);
+
+        // Let any tighter binding operators (or right associative ones of the
+        // same precedence) claim the right hand side first
+        while let Some(next) = next_operator(rest) {
+            if next.precedence > current.precedence {
+                rhs = climb_from(rhs, rest, current.precedence + 1);
+            } else if next.precedence == current.precedence && next.assoc == Assoc::Right {
+                rhs = climb_from(rhs, rest, current.precedence);
+            } else {
+                break;
+            }
+        }
+
+        let (start, end) = (lhs.span.start, rhs.span.end);
+        let infix = Node::Infix {
+            lhs: Box::new(lhs),
+            op,
+            rhs: Box::new(rhs),
+        };
+        lhs = Spanned::new(infix, start, end);
+    }
+    lhs
+}
+
+/// Applies the prefix operators of an operand, after letting any operators
+/// that bind tighter than them claim the operand
+fn resolve_operand<I>(operand: Operand, rest: &mut Peekable<I>) -> SpannedNode
+where
+    I: Iterator<Item = (String, Operand)>,
+{
+    if operand.prefix.is_empty() {
+        return operand.node;
+    }
+    let inner = climb_from(operand.node, rest, PREFIX_PRECEDENCE + 1);
+    operand.prefix.into_iter().rev().fold(inner, |rhs, op| {
+        let (start, end) = (op.span.start, rhs.span.end);
+        let prefix = Node::Prefix {
+            op: op.node,
+            rhs: Box::new(rhs),
+        };
+        Spanned::new(prefix, start, end)
+    })
+}
+
+fn next_operator<I>(rest: &mut Peekable<I>) -> Option<OperatorInfo>
+where
+    I: Iterator<Item = (String, Operand)>,
+{
+    rest.peek().map(|(op, _)| operator_info(op))
+}
@@ -1,5 +1,6 @@
 use pest::{
     error::{Error as PestError, InputLocation},
+    iterators::Pair,
     Parser,
 };
 use pest_derive::*;
@@ -25,22 +26,47 @@ pub enum Error<'input> {
     UnknownKeyword,
     UnknownGrouping,
     UnknownSymbol(&'input str),
+    UnexpectedChar(char),
+    /// A string literal missing its closing quote
+    UnclosedString,
+    InvalidEscape(&'input str),
 
     UnknownErr,
 }
 
+impl<'input> std::fmt::Display for Error<'input> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Error::PestErr(_) => write!(f, "Invalid token"),
+            Error::InvalidInteger => write!(f, "Invalid integer"),
+            Error::InvalidHex => write!(f, "Invalid hexadecimal integer"),
+            Error::InvalidBinary => write!(f, "Invalid binary integer"),
+            Error::InvalidFloat => write!(f, "Invalid float"),
+            Error::UnknownKeyword => write!(f, "Unknown keyword"),
+            Error::UnknownGrouping => write!(f, "Unknown bracket"),
+            Error::UnknownSymbol(sym) => write!(f, "Unknown symbol `{}`", sym),
+            Error::UnexpectedChar(ch) => write!(f, "Unexpected character {:?}", ch),
+            Error::UnclosedString => write!(f, "Unclosed string"),
+            Error::InvalidEscape(escape) => write!(f, "Invalid escape sequence `{}`", escape),
+            Error::UnknownErr => write!(f, "Could not read the input"),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub enum Token<'input> {
     Ident(&'input str),
     StringLit(&'input str),
     Integer(rug::Integer),
     Float(rug::Float),
+    /// The imaginary part of a complex number
+    Imaginary(rug::Float),
 
     Operator(&'input str),
 
     Let,
     Fun,
-    Prev,
+    Prev(crate::ast::Recall),
     Sh,
 
     Comma,
@@ -58,10 +84,50 @@ pub enum Token<'input> {
     RAngleBracket,
 }
 
-/// A span holding the start and end of a token
+impl<'input> Token<'input> {
+    /// A human readable description of the token, used in errors
+    pub fn describe(&self) -> String {
+        match self {
+            Token::Ident(name) => format!("identifier `{}`", name),
+            Token::StringLit(_) => "string".to_owned(),
+            Token::Integer(_) | Token::Float(_) | Token::Imaginary(_) => "number".to_owned(),
+            Token::Operator(op) => format!("operator `{}`", op),
+            Token::Let => "`let`".to_owned(),
+            Token::Fun => "`fn`".to_owned(),
+            Token::Prev(_) => "`#`".to_owned(),
+            Token::Sh => "`$`".to_owned(),
+            Token::Comma => "`,`".to_owned(),
+            Token::Semicolon => "`;`".to_owned(),
+            Token::Equals => "`=`".to_owned(),
+            Token::LParen => "`(`".to_owned(),
+            Token::RParen => "`)`".to_owned(),
+            Token::LBracket => "`[`".to_owned(),
+            Token::RBracket => "`]`".to_owned(),
+            Token::LBrace => "`{`".to_owned(),
+            Token::RBrace => "`}`".to_owned(),
+            Token::LAngleBracket => "`<`".to_owned(),
+            Token::RAngleBracket => "`>`".to_owned(),
+        }
+    }
+}
+
+/// A span holding the start and end of a token, as byte offsets into the input
+#[derive(Clone, Copy, PartialEq)]
 pub struct Span {
-    pub start: u16,
-    pub end: u16,
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// The line and column of the start of the span, see `line_col`
+    pub fn start_line_col(&self, source: &str) -> (usize, usize) {
+        line_col(source, self.start)
+    }
+
+    /// The line and column of the end of the span, see `line_col`
+    pub fn end_line_col(&self, source: &str) -> (usize, usize) {
+        line_col(source, self.end)
+    }
 }
 
 impl std::fmt::Debug for Span {
@@ -70,15 +136,44 @@ impl std::fmt::Debug for Span {
     }
 }
 
+impl From<(usize, usize)> for Span {
+    fn from((start, end): (usize, usize)) -> Self {
+        Span { start, end }
+    }
+}
+
 impl<'a> From<pest::Span<'a>> for Span {
     fn from(pest_span: pest::Span) -> Self {
         Span {
-            start: pest_span.start() as u16,
-            end: pest_span.end() as u16,
+            start: pest_span.start(),
+            end: pest_span.end(),
         }
     }
 }
 
+/// Moves a byte offset back to the start of the character it is in, or to
+/// the end of `source` if it is past it
+pub fn floor_char_boundary(source: &str, offset: usize) -> usize {
+    let mut offset = offset.min(source.len());
+    while !source.is_char_boundary(offset) {
+        offset -= 1;
+    }
+    offset
+}
+
+/// The line and column of a byte offset into `source`, both counting from zero
+///
+/// Columns count characters rather than bytes, and lines may end with `\n` or `\r\n`
+pub fn line_col(source: &str, offset: usize) -> (usize, usize) {
+    let offset = floor_char_boundary(source, offset);
+    let before = &source[..offset];
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (
+        before.matches('\n').count(),
+        before[line_start..].chars().count(),
+    )
+}
+
 #[derive(Debug)]
 pub struct SpannedToken<'a>(pub Token<'a>, pub Span);
 
@@ -89,195 +184,299 @@ impl<'a> SpannedToken<'a> {
 }
 
 #[derive(Debug)]
-pub struct SpannedError<'a>(pub Error<'a>, pub Option<Span>);
+pub struct SpannedError<'a>(pub Error<'a>, pub Span);
 
 impl<'a> SpannedError<'a> {
     pub fn spanned<O, S: Into<Span>>(err: Error, span: S) -> Result<O, SpannedError> {
-        Err(SpannedError(err, Some(span.into())))
+        Err(SpannedError(err, span.into()))
     }
+}
 
-    pub fn spanless<O>(err: Error) -> Result<O, SpannedError> {
-        Err(SpannedError(err, None))
+/// Replaces escape sequences in the contents of a string literal with the characters they represent
+///
+/// Supports `\"`, `\\`, `\n`, `\t`, `\r`, `\0` and unicode escapes such as `\u{1F600}`
+pub fn unescape(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    let mut chars = input.chars();
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            output.push(ch);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => output.push('\n'),
+            Some('t') => output.push('\t'),
+            Some('r') => output.push('\r'),
+            Some('0') => output.push('\0'),
+            Some('u') => {
+                let code: String = chars
+                    .by_ref()
+                    .skip_while(|c| *c == '{')
+                    .take_while(|c| *c != '}')
+                    .collect();
+                let ch = u32::from_str_radix(&code, 16)
+                    .ok()
+                    .and_then(std::char::from_u32)
+                    .unwrap_or(std::char::REPLACEMENT_CHARACTER);
+                output.push(ch);
+            }
+            Some(other) => output.push(other),
+            None => output.push('\\'),
+        }
     }
+    output
 }
 
 /// Tokenizes a string into a stream of tokens
-pub fn tokenize(input: &str) -> Result<Vec<SpannedToken>, SpannedError> {
+pub fn tokenize(input: &str) -> Result<Vec<SpannedToken>, Vec<SpannedError>> {
+    tokenize_with_precision(input, crate::ast::DEFAULT_PRECISION)
+}
+
+/// Tokenizes a string into a stream of tokens, parsing float literals with the given precision
+///
+/// Lexing continues past invalid tokens so that all of them are reported
+pub fn tokenize_with_precision(
+    input: &str,
+    prec: u32,
+) -> Result<Vec<SpannedToken>, Vec<SpannedError>> {
     let mut token_list = match Lex::parse(Rule::token_list, input) {
         Ok(token_list) => token_list,
-        Err(e) => return SpannedError::spanless(Error::PestErr(e)),
+        Err(e) => {
+            let span = span_from_loc(e.location.clone());
+            return Err(vec![SpannedError(Error::PestErr(e), span.into())]);
+        }
     };
     let tokens = match token_list.next() {
         Some(tokens) => tokens.into_inner(),
-        None => return SpannedError::spanless(Error::UnknownErr),
+        None => return Err(vec![SpannedError(Error::UnknownErr, (0, input.len()).into())]),
     };
 
     let mut output_tokens = Vec::new();
-
+    let mut errors = Vec::new();
+    // Whether each open bracket is a `<`
+    let mut open = Vec::new();
     for token in tokens {
-        let tok = match token.as_rule() {
-            Rule::ident => SpannedToken::new(Token::Ident(token.as_str()), token.as_span()),
-            Rule::string => {
-                let str = token.as_str();
+        if token.as_rule() == Rule::invalid_string {
+            errors.extend(string_errors(token));
+            continue;
+        }
+        match lex_token(token, prec) {
+            Ok(Some(tok)) => push_token(&mut output_tokens, &mut open, tok),
+            Ok(None) => {}
+            Err(e) => errors.push(e),
+        }
+    }
+    if errors.is_empty() {
+        Ok(output_tokens)
+    } else {
+        Err(errors)
+    }
+}
 
-                SpannedToken::new(Token::StringLit(&str[1..str.len() - 1]), token.as_span())
-            }
-            Rule::integer => {
-                let stripped_int = token.as_str().replace('_', "");
-                let int_token = match token.into_inner().next() {
-                    Some(tok) => tok,
-                    None => panic!("Probably not valid state"),
-                };
-
-                let int = match int_token.as_rule() {
-                    Rule::decimal_int => match stripped_int.parse() {
-                        Ok(i) => i,
+/// The errors in a string literal that failed to lex, each invalid escape
+/// sequence and a missing closing quote
+fn string_errors(token: Pair<Rule>) -> Vec<SpannedError> {
+    let span = token.as_span();
+    let mut errors = Vec::new();
+    let mut closed = false;
+    for part in token.into_inner() {
+        match part.as_rule() {
+            Rule::bad_escape => errors.push(SpannedError(
+                Error::InvalidEscape(part.as_str()),
+                part.as_span().into(),
+            )),
+            Rule::string_end => closed = true,
+            _ => {}
+        }
+    }
+    if !closed {
+        errors.push(SpannedError(Error::UnclosedString, span.into()));
+    }
+    errors
+}
+
+/// Converts a single pair from the lexer into a token, `None` for separators
+fn lex_token(token: Pair<Rule>, prec: u32) -> Result<Option<SpannedToken>, SpannedError> {
+    let tok = match token.as_rule() {
+        Rule::ident => SpannedToken::new(Token::Ident(token.as_str()), token.as_span()),
+        Rule::string => {
+            let str = token.as_str();
+
+            SpannedToken::new(Token::StringLit(&str[1..str.len() - 1]), token.as_span())
+        }
+        Rule::integer => {
+            let stripped_int = token.as_str().replace('_', "");
+            let int_token = match token.into_inner().next() {
+                Some(tok) => tok,
+                None => panic!("Probably not valid state"),
+            };
+
+            let int = match int_token.as_rule() {
+                Rule::decimal_int => match stripped_int.parse() {
+                    Ok(i) => i,
+                    Err(_) => {
+                        return SpannedError::spanned(Error::InvalidInteger, int_token.as_span())
+                    }
+                },
+                Rule::hex_int => {
+                    let stripped_int = if let Some('x') = stripped_int.chars().skip(1).next() {
+                        &stripped_int[2..]
+                    } else {
+                        &stripped_int[..stripped_int.len() - 1]
+                    };
+                    match rug::Integer::from_str_radix(stripped_int, 16) {
+                        Ok(int) => int,
                         Err(_) => {
-                            return SpannedError::spanned(Error::InvalidInteger, int_token.as_span())
-                        }
-                    },
-                    Rule::hex_int => {
-                        let stripped_int = if let Some('x') = stripped_int.chars().skip(1).next() {
-                            &stripped_int[2..]
-                        } else {
-                            &stripped_int[..stripped_int.len() - 1]
-                        };
-                        match rug::Integer::from_str_radix(stripped_int, 16) {
-                            Ok(int) => int,
-                            Err(_) => {
-                                return SpannedError::spanned(Error::InvalidHex, int_token.as_span())
-                            }
+                            return SpannedError::spanned(Error::InvalidHex, int_token.as_span())
                         }
                     }
-                    Rule::binary_int => {
-                        let stripped_int = &stripped_int[2..];
-                        match rug::Integer::from_str_radix(stripped_int, 2) {
-                            Ok(int) => int,
-                            Err(_) => {
-                                return SpannedError::spanned(
-                                    Error::InvalidBinary,
-                                    int_token.as_span(),
-                                )
-                            }
+                }
+                Rule::binary_int => {
+                    let stripped_int = &stripped_int[2..];
+                    match rug::Integer::from_str_radix(stripped_int, 2) {
+                        Ok(int) => int,
+                        Err(_) => {
+                            return SpannedError::spanned(
+                                Error::InvalidBinary,
+                                int_token.as_span(),
+                            )
                         }
                     }
-                    _ => unreachable!(),
-                };
-                let mult = if detect_negative(&output_tokens) {
-                    // Remove the negative sign from the generated tokens
-                    output_tokens.remove(output_tokens.len() - 1);
-                    -1
-                } else {
-                    1
-                };
-                let tok = Token::Integer(int * mult);
-                SpannedToken::new(tok, int_token.as_span())
-            }
-            Rule::float => {
-                let stripped_float = token.as_str().replace('_', "");
-                let incomplete_float = match rug::Float::parse(stripped_float) {
-                    Ok(i) => i,
-                    Err(_) => return SpannedError::spanned(Error::InvalidFloat, token.as_span()),
-                };
-                let float = rug::Float::with_val(53, incomplete_float);
-                let mult = if detect_negative(&output_tokens) {
-                    // Remove the negative sign from the generated tokens
-                    output_tokens.remove(output_tokens.len() - 1);
-                    -1.
-                } else {
-                    1.
-                };
-                let tok = Token::Float(float * mult);
-                SpannedToken::new(tok, token.as_span())
-            }
-            Rule::operator => {
-                // TODO: Validate operator?
-                SpannedToken::new(Token::Operator(token.as_str()), token.as_span())
-            }
-            Rule::keyword => {
-                let tok = match token.as_str() {
-                    "let" => Token::Let,
-                    "fn" => Token::Fun,
-                    "#" => Token::Prev,
-                    "$" => Token::Sh,
-                    _ => return SpannedError::spanned(Error::UnknownKeyword, token.as_span()),
-                };
-                SpannedToken::new(tok, token.as_span())
-            }
-            Rule::grouping_char => {
-                let tok = match token.as_str() {
-                    "(" => Token::LParen,
-                    ")" => Token::RParen,
-                    "[" => Token::LBracket,
-                    "]" => Token::RBracket,
-                    "{" => Token::LBrace,
-                    "}" => Token::RBrace,
-                    "<" => Token::LAngleBracket,
-                    ">" => Token::RAngleBracket,
-                    _ => return SpannedError::spanned(Error::UnknownGrouping, token.as_span()),
-                };
-                SpannedToken::new(tok, token.as_span())
-            }
-            Rule::seperator => {
-                let tok = match token.as_str() {
-                    " " | "\n" => continue,
-                    "," => Token::Comma,
-                    ";" => Token::Semicolon,
-                    sym => return SpannedError::spanned(Error::UnknownSymbol(sym), token.as_span()),
-                };
-                SpannedToken::new(tok, token.as_span())
-            }
-            Rule::symbol => {
-                let tok = match token.as_str() {
-                    "=" => Token::Equals,
-                    sym => return SpannedError::spanned(Error::UnknownSymbol(sym), token.as_span()),
-                };
-                SpannedToken::new(tok, token.as_span())
+                }
+                _ => unreachable!(),
+            };
+            SpannedToken::new(Token::Integer(int), int_token.as_span())
+        }
+        Rule::float => {
+            let stripped_float = token.as_str().replace('_', "");
+            let incomplete_float = match rug::Float::parse(stripped_float) {
+                Ok(i) => i,
+                Err(_) => return SpannedError::spanned(Error::InvalidFloat, token.as_span()),
+            };
+            let float = rug::Float::with_val(prec, incomplete_float);
+            SpannedToken::new(Token::Float(float), token.as_span())
+        }
+        Rule::imaginary => {
+            let text = token.as_str();
+            let stripped_float = text[..text.len() - 1].replace('_', "");
+            let incomplete_float = match rug::Float::parse(stripped_float) {
+                Ok(i) => i,
+                Err(_) => return SpannedError::spanned(Error::InvalidFloat, token.as_span()),
+            };
+            let float = rug::Float::with_val(prec, incomplete_float);
+            SpannedToken::new(Token::Imaginary(float), token.as_span())
+        }
+        Rule::operator => {
+            // TODO: Validate operator?
+            SpannedToken::new(Token::Operator(token.as_str()), token.as_span())
+        }
+        Rule::prev => {
+            use crate::ast::Recall;
+            let recall = match &token.as_str()[1..] {
+                hashes if hashes.chars().all(|c| c == '#') => {
+                    Ok(Recall::Relative(hashes.len() + 1))
+                }
+                n if n.starts_with('-') => n[1..].parse().map(Recall::Relative),
+                n => n.parse().map(Recall::Absolute),
+            };
+            match recall {
+                Ok(recall) => SpannedToken::new(Token::Prev(recall), token.as_span()),
+                Err(_) => return SpannedError::spanned(Error::InvalidInteger, token.as_span()),
             }
-            Rule::EOI => break,
-
-            // Impossible (silent rules)
-            Rule::token
-            | Rule::ident_char
-            | Rule::ident_start
-            | Rule::digit
-            | Rule::str_inner
-            | Rule::str_char
-            | Rule::number
-            | Rule::hex_digit
-            | Rule::hex_int
-            | Rule::binary_int
-            | Rule::decimal_int
-            | Rule::token_list => unreachable!(),
-        };
-        output_tokens.push(tok);
-    }
-    Ok(output_tokens)
+        }
+        Rule::keyword => {
+            let tok = match token.as_str() {
+                "let" => Token::Let,
+                "fn" => Token::Fun,
+                "$" => Token::Sh,
+                _ => return SpannedError::spanned(Error::UnknownKeyword, token.as_span()),
+            };
+            SpannedToken::new(tok, token.as_span())
+        }
+        Rule::grouping_char => {
+            let tok = match token.as_str() {
+                "(" => Token::LParen,
+                ")" => Token::RParen,
+                "[" => Token::LBracket,
+                "]" => Token::RBracket,
+                "{" => Token::LBrace,
+                "}" => Token::RBrace,
+                "<" => Token::LAngleBracket,
+                ">" => Token::RAngleBracket,
+                _ => return SpannedError::spanned(Error::UnknownGrouping, token.as_span()),
+            };
+            SpannedToken::new(tok, token.as_span())
+        }
+        Rule::seperator => {
+            let tok = match token.as_str() {
+                " " | "\t" | "\n" | "\r\n" | "\r" => return Ok(None),
+                "," => Token::Comma,
+                ";" => Token::Semicolon,
+                sym => return SpannedError::spanned(Error::UnknownSymbol(sym), token.as_span()),
+            };
+            SpannedToken::new(tok, token.as_span())
+        }
+        Rule::symbol => {
+            let tok = match token.as_str() {
+                "=" => Token::Equals,
+                sym => return SpannedError::spanned(Error::UnknownSymbol(sym), token.as_span()),
+            };
+            SpannedToken::new(tok, token.as_span())
+        }
+        Rule::EOI => return Ok(None),
+        Rule::invalid => {
+            let ch = token.as_str().chars().next().unwrap_or_default();
+            return SpannedError::spanned(Error::UnexpectedChar(ch), token.as_span());
+        }
+
+        // Impossible (silent rules)
+        Rule::token
+        | Rule::ident_char
+        | Rule::ident_start
+        | Rule::digit
+        | Rule::str_inner
+        | Rule::str_char
+        | Rule::number
+        | Rule::exponent
+        | Rule::hex_digit
+        | Rule::hex_int
+        | Rule::binary_int
+        | Rule::decimal_int
+        | Rule::bad_escape
+        | Rule::string_end
+        | Rule::token_list => unreachable!(),
+        // Handled by `tokenize_with_precision`
+        Rule::invalid_string => unreachable!(),
+    };
+    Ok(Some(tok))
 }
 
-fn detect_negative(prev_tokens: &[SpannedToken]) -> bool {
-    let mut negative = false;
-    // Is it previous token a negative sign?
-    if let Some(i) = prev_tokens.len().checked_sub(1) {
-        if let Some(SpannedToken(Token::Operator(op), _)) = prev_tokens.get(i) {
-            if *op == "-" {
-                negative = true;
-                // Is the number before the negative sign a number?
-                // If it is, we can assume that it is subtraction
-                if let Some(i) = prev_tokens.len().checked_sub(2) {
-                    if let Some(token) = prev_tokens.get(i) {
-                        match token {
-                            SpannedToken(Token::Integer(_), _)
-                            | SpannedToken(Token::Float(_), _) => {
-                                // It is subtaction, do not negate
-                                negative = false;
-                            }
-                            _ => {}
-                        }
-                    }
-                }
-            }
+/// Adds a token to the output, splitting `>>` into two `>` where it closes
+/// angle brackets so that `f<g<x>>` is a nested call while `x >> 1` is a shift
+fn push_token<'a>(output: &mut Vec<SpannedToken<'a>>, open: &mut Vec<bool>, tok: SpannedToken<'a>) {
+    match tok.0 {
+        Token::LParen | Token::LBracket | Token::LBrace => open.push(false),
+        Token::LAngleBracket => open.push(true),
+        Token::RParen | Token::RBracket | Token::RBrace | Token::RAngleBracket => {
+            open.pop();
+        }
+        Token::Operator(">>") if open.last() == Some(&true) => {
+            let Span { start, end } = tok.1;
+            open.pop();
+            open.pop();
+            let first = Span {
+                start,
+                end: start + 1,
+            };
+            let second = Span {
+                start: start + 1,
+                end,
+            };
+            output.push(SpannedToken(Token::RAngleBracket, first));
+            output.push(SpannedToken(Token::RAngleBracket, second));
+            return;
         }
+        _ => {}
     }
-    negative
+    output.push(tok);
 }
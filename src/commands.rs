@@ -0,0 +1,192 @@
+use slide::eval::{Function, LoadMode};
+
+use crate::SlideContext;
+
+/// Commands handled by the REPL rather than the evaluator, along with their
+/// arguments and a description for `:help`
+pub const COMMANDS: &[(&str, &str, &str)] = &[
+    (":vars", "", "List variables and their values"),
+    (":fns", "", "List functions and their parameters"),
+    (":del", "name", "Delete a variable or function"),
+    (":reset", "", "Delete all variables, functions and results"),
+    (":prec", "[bits]", "Show or set the precision of floats"),
+    (":save", "path", "Save variables and functions to a file"),
+    (
+        ":load",
+        "[--replace] path",
+        "Load variables and functions from a file",
+    ),
+    (":ast", "expr", "Show the syntax tree of an expression"),
+    (":tokens", "expr", "Show the tokens of an expression"),
+    (":help", "", "Show this help"),
+    (":quit", "", "Exit slide"),
+];
+
+impl SlideContext {
+    /// Runs a meta-command such as `:vars`, `first_line` is as in `eval_line`
+    ///
+    /// Returns false if there were any errors
+    pub fn run_command(&mut self, input: &str, first_line: usize) -> bool {
+        let (name, arg) = match input.find(char::is_whitespace) {
+            Some(i) => (&input[..i], input[i..].trim()),
+            None => (input, ""),
+        };
+        match name {
+            ":vars" => self.list_vars(),
+            ":fns" => self.list_functions(),
+            ":del" => return self.delete(arg),
+            ":reset" => {
+                self.eval_ctx.borrow_mut().reset();
+                self.print_result("Deleted all variables, functions and results");
+            }
+            ":prec" => return self.set_precision(arg),
+            ":save" => return self.save(arg),
+            ":load" => {
+                let (flag, path) = match arg.find(char::is_whitespace) {
+                    Some(i) => (&arg[..i], arg[i..].trim()),
+                    None => (arg, ""),
+                };
+                return match flag {
+                    "--replace" => self.load(path, LoadMode::Replace),
+                    _ => self.load(arg, LoadMode::Merge),
+                };
+            }
+            ":ast" => match self.parse(arg, first_line) {
+                Some(nodes) => {
+                    for l in format!("{:#?}", nodes).lines() {
+                        self.print_result(l);
+                    }
+                }
+                None => return false,
+            },
+            ":tokens" => match self.tokenize(arg, first_line) {
+                Some(tokens) => {
+                    for token in tokens {
+                        self.print_result(&format!("{:?}", token));
+                    }
+                }
+                None => return false,
+            },
+            ":help" => {
+                for (name, args, help) in COMMANDS {
+                    let usage = format!("{} {}", name, args);
+                    self.print_result(&format!("{:<14} {}", usage, help));
+                }
+            }
+            ":quit" => self.quit = true,
+            _ => {
+                self.print_error(&format!("Unknown command `{}`, see `:help`", name));
+                return false;
+            }
+        }
+        true
+    }
+
+    fn list_vars(&self) {
+        let eval_ctx = self.eval_ctx.borrow();
+        let mut values: Vec<_> = eval_ctx.values().collect();
+        if values.is_empty() {
+            self.print_result("No variables defined");
+            return;
+        }
+        values.sort_by_key(|(name, _)| *name);
+        let (style, prec) = (eval_ctx.rational_style, eval_ctx.precision());
+        for (name, value) in values {
+            self.print_result(&format!("{} = {}", name, value.format(style, prec)));
+        }
+    }
+
+    fn list_functions(&self) {
+        let eval_ctx = self.eval_ctx.borrow();
+        let mut user_defined = Vec::new();
+        let mut builtins = Vec::new();
+        for (name, func) in eval_ctx.functions() {
+            match func {
+                Function::UserDefined { .. } => user_defined.push(func.to_string()),
+                Function::Builtin(_) => builtins.push(name),
+            }
+        }
+        user_defined.sort();
+        builtins.sort();
+        for func in user_defined {
+            self.print_result(&func);
+        }
+        self.print_result(&format!("builtin {}", builtins.join(", ")));
+    }
+
+    fn delete(&mut self, name: &str) -> bool {
+        if name.is_empty() {
+            self.print_error("Expected the name of a variable or function to delete");
+            return false;
+        }
+        if self.eval_ctx.borrow_mut().remove(name) {
+            self.print_result(&format!("Deleted `{}`", name));
+            true
+        } else {
+            self.print_error(&format!("No variable or function named `{}`", name));
+            false
+        }
+    }
+
+    fn save(&self, path: &str) -> bool {
+        if path.is_empty() {
+            self.print_error("Expected a path to save to");
+            return false;
+        }
+        match self.eval_ctx.borrow().save(path) {
+            Ok(skipped) => {
+                for name in skipped {
+                    self.print_error(&format!("Skipped `{}`, its value cannot be saved", name));
+                }
+                self.print_result(&format!("Saved to {}", path));
+                true
+            }
+            Err(e) => {
+                self.print_error(&format!("Could not save to {}: {}", path, e));
+                false
+            }
+        }
+    }
+
+    fn load(&mut self, path: &str, mode: LoadMode) -> bool {
+        if path.is_empty() {
+            self.print_error("Expected a path to load from");
+            return false;
+        }
+        match self.eval_ctx.borrow_mut().load(path, mode) {
+            Ok(()) => {
+                self.print_result(&format!("Loaded {}", path));
+                true
+            }
+            Err(e) => {
+                self.print_error(&format!("Could not load {}: {}", path, e));
+                false
+            }
+        }
+    }
+
+    /// Prints the current precision, or sets a new one
+    fn set_precision(&mut self, arg: &str) -> bool {
+        if arg.is_empty() {
+            let prec = self.eval_ctx.borrow().precision();
+            self.print_result(&format!("{} bits", prec));
+            return true;
+        }
+        match arg.parse() {
+            Ok(prec) => match self.eval_ctx.borrow_mut().set_precision(prec) {
+                Ok(()) => {
+                    self.print_result(&format!("{} bits", prec));
+                    true
+                }
+                Err(e) => {
+                    self.print_error(&e.to_string());
+                    false
+                }
+            },
+            Err(_) => {
+                self.print_error(&format!("Expected a number of bits, found `{}`", arg));
+                false
+            }
+        }
+    }
+}
@@ -0,0 +1,254 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+
+use rustyline::config::EditMode;
+
+use crate::history::HistorySettings;
+use crate::token::{self, Token};
+
+/// The strings shown before input, results and errors
+#[derive(Debug, Clone)]
+pub struct Prompts {
+    pub input: String,
+    /// Shown while reading the rest of an incomplete expression
+    pub continuation: String,
+    pub result: String,
+    pub error: String,
+}
+
+impl Default for Prompts {
+    fn default() -> Prompts {
+        Prompts {
+            input: "<< ".to_owned(),
+            continuation: ".. ".to_owned(),
+            result: "=> ".to_owned(),
+            error: "=# ".to_owned(),
+        }
+    }
+}
+
+/// ANSI SGR codes used to colour each kind of token, such as `33` or `1;34`
+#[derive(Debug, Clone)]
+pub struct Theme {
+    pub number: String,
+    pub string: String,
+    /// Identifiers that refer to a variable, constant or function
+    pub ident: String,
+    pub keyword: String,
+    pub operator: String,
+    pub bracket: String,
+    pub hint: String,
+}
+
+impl Default for Theme {
+    fn default() -> Theme {
+        Theme {
+            number: "33".to_owned(),
+            string: "32".to_owned(),
+            ident: "36".to_owned(),
+            keyword: "35".to_owned(),
+            operator: "1".to_owned(),
+            bracket: "34".to_owned(),
+            hint: "2".to_owned(),
+        }
+    }
+}
+
+impl Theme {
+    /// The colour of a token, `None` for tokens shown as they are
+    pub fn token_color(&self, token: &Token, known: bool) -> Option<&str> {
+        Some(match token {
+            Token::Integer(_) | Token::Float(_) | Token::Imaginary(_) => &self.number,
+            Token::StringLit(_) => &self.string,
+            Token::Ident(_) if known => &self.ident,
+            Token::Ident(_) => return None,
+            Token::Let | Token::Fun | Token::Prev(_) | Token::Sh => &self.keyword,
+            Token::Operator(_) | Token::Equals => &self.operator,
+            Token::LParen
+            | Token::RParen
+            | Token::LBracket
+            | Token::RBracket
+            | Token::LBrace
+            | Token::RBrace
+            | Token::LAngleBracket
+            | Token::RAngleBracket => &self.bracket,
+            Token::Comma | Token::Semicolon => return None,
+        })
+    }
+}
+
+/// Settings read from the config file, which command line arguments override
+pub struct SlideConfig {
+    pub precision: Option<u32>,
+    /// Whether input is highlighted
+    pub colors: bool,
+    pub theme: Theme,
+    pub edit_mode: EditMode,
+    pub prompts: Prompts,
+    pub history: HistorySettings,
+    /// Set by `history = false`, which takes priority over `history_file`
+    /// wherever each appears in the file
+    history_disabled: bool,
+}
+
+impl Default for SlideConfig {
+    fn default() -> SlideConfig {
+        SlideConfig {
+            precision: None,
+            colors: true,
+            theme: Theme::default(),
+            edit_mode: EditMode::Emacs,
+            prompts: Prompts::default(),
+            history: HistorySettings::default(),
+            history_disabled: false,
+        }
+    }
+}
+
+/// Slide's directory within an XDG base directory, following the XDG base
+/// directory spec
+///
+/// `var` names the environment variable holding the base directory, and
+/// `fallback` is used relative to `$HOME` when it is unset or empty
+pub fn xdg_dir(var: &str, fallback: &str) -> Option<PathBuf> {
+    let base = match env::var_os(var) {
+        Some(dir) if !dir.is_empty() => PathBuf::from(dir),
+        _ => PathBuf::from(env::var_os("HOME")?).join(fallback),
+    };
+    Some(base.join("slide"))
+}
+
+/// The directory for slide's configuration
+pub fn config_dir() -> Option<PathBuf> {
+    xdg_dir("XDG_CONFIG_HOME", ".config")
+}
+
+/// The file evaluated before the prompt appears
+pub fn init_path() -> Option<PathBuf> {
+    config_dir().map(|dir| dir.join("init.slide"))
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    match value {
+        "true" | "yes" | "on" => Ok(true),
+        "false" | "no" | "off" => Ok(false),
+        _ => Err(format!("expected true or false, found `{}`", value)),
+    }
+}
+
+/// Checks that a colour is SGR codes such as `1;32`, since it is written
+/// into escape sequences as is
+fn parse_color(value: String) -> Result<String, String> {
+    if !value.is_empty() && value.chars().all(|ch| ch.is_ascii_digit() || ch == ';') {
+        Ok(value)
+    } else {
+        Err(format!(
+            "expected SGR codes such as `1;32`, found `{}`",
+            value
+        ))
+    }
+}
+
+fn parse_number<T: std::str::FromStr>(value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("expected a number, found `{}`", value))
+}
+
+impl SlideConfig {
+    /// Reads the config file if there is one
+    ///
+    /// Returns the config along with warnings about any invalid lines, which are ignored
+    pub fn load() -> (SlideConfig, Vec<String>) {
+        let mut config = SlideConfig::default();
+        let path = match config_dir() {
+            Some(dir) => dir.join("config"),
+            None => return (config, vec![]),
+        };
+        if !path.exists() {
+            return (config, vec![]);
+        }
+        let warnings = match fs::read_to_string(&path) {
+            Ok(source) => config.apply(&source),
+            Err(e) => vec![format!("could not be read: {}", e)],
+        };
+        let warnings = warnings
+            .into_iter()
+            .map(|warning| format!("{}: {}", path.display(), warning))
+            .collect();
+        (config, warnings)
+    }
+
+    /// Applies each `key = value` line of a config file, returning warnings
+    /// about invalid lines
+    ///
+    /// Values may be quoted to keep surrounding spaces, and lines starting
+    /// with `#` are comments
+    pub fn apply(&mut self, source: &str) -> Vec<String> {
+        let mut warnings = Vec::new();
+        for (i, line) in source.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let result = match line.find('=') {
+                Some(eq) => {
+                    let key = line[..eq].trim();
+                    let value = line[eq + 1..].trim();
+                    let value =
+                        if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+                            token::unescape(&value[1..value.len() - 1])
+                        } else {
+                            value.to_owned()
+                        };
+                    self.set(key, value)
+                }
+                None => Err("expected `key = value`".to_owned()),
+            };
+            if let Err(e) = result {
+                warnings.push(format!("line {}: {}", i + 1, e));
+            }
+        }
+        if self.history_disabled {
+            self.history.path = None;
+        }
+        warnings
+    }
+
+    fn set(&mut self, key: &str, value: String) -> Result<(), String> {
+        match key {
+            "precision" => {
+                let prec = parse_number(&value)?;
+                if prec < rug::float::prec_min() || prec > rug::float::prec_max() {
+                    return Err(format!("invalid precision of {} bits", prec));
+                }
+                self.precision = Some(prec);
+            }
+            "colors" => self.colors = parse_bool(&value)?,
+            "edit_mode" => {
+                self.edit_mode = match value.as_str() {
+                    "emacs" => EditMode::Emacs,
+                    "vi" => EditMode::Vi,
+                    _ => return Err(format!("expected emacs or vi, found `{}`", value)),
+                }
+            }
+            "prompt" => self.prompts.input = value,
+            "continuation_prompt" => self.prompts.continuation = value,
+            "result_prefix" => self.prompts.result = value,
+            "error_prefix" => self.prompts.error = value,
+            "history" => self.history_disabled = !parse_bool(&value)?,
+            "history_file" => self.history.path = Some(value.into()),
+            "history_size" => self.history.max_size = parse_number(&value)?,
+            "color.number" => self.theme.number = parse_color(value)?,
+            "color.string" => self.theme.string = parse_color(value)?,
+            "color.ident" => self.theme.ident = parse_color(value)?,
+            "color.keyword" => self.theme.keyword = parse_color(value)?,
+            "color.operator" => self.theme.operator = parse_color(value)?,
+            "color.bracket" => self.theme.bracket = parse_color(value)?,
+            "color.hint" => self.theme.hint = parse_color(value)?,
+            _ => return Err(format!("unknown setting `{}`", key)),
+        }
+        Ok(())
+    }
+}
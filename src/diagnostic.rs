@@ -0,0 +1,138 @@
+use crate::token::{floor_char_boundary, line_col};
+
+/// A part of the source that an error points at, with an explanation
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    /// Byte offsets into the source
+    pub start: usize,
+    pub end: usize,
+    pub message: String,
+}
+
+/// An error along with the parts of the source it refers to, and any extra
+/// notes or help on fixing it
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+    pub help: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new<S: Into<String>>(message: S) -> Diagnostic {
+        Diagnostic {
+            message: message.into(),
+            labels: vec![],
+            notes: vec![],
+            help: vec![],
+        }
+    }
+
+    pub fn label<S: Into<String>>(mut self, start: usize, end: usize, message: S) -> Diagnostic {
+        self.labels.push(Label {
+            start,
+            end,
+            message: message.into(),
+        });
+        self
+    }
+
+    pub fn note<S: Into<String>>(mut self, note: S) -> Diagnostic {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn help<S: Into<String>>(mut self, help: S) -> Diagnostic {
+        self.help.push(help.into());
+        self
+    }
+
+    /// Renders the diagnostic as lines of text, showing and underlining the
+    /// lines of `source` that the labels point into
+    ///
+    /// `first_line` is the line number shown for the first line of `source`,
+    /// for sources that are part of a larger file
+    ///
+    /// ```text
+    /// error: unexpected `)`
+    ///   |
+    /// 1 | 1 + )
+    ///   |     ^ expected a number, identifier or `(`
+    ///   = help: ...
+    /// ```
+    pub fn render(&self, source: &str, first_line: usize) -> Vec<String> {
+        let mut out = vec![format!("error: {}", self.message)];
+
+        let mut labels: Vec<_> = self
+            .labels
+            .iter()
+            .map(|label| (LabelPosition::new(source, label), label))
+            .collect();
+        labels.sort_by_key(|(pos, _)| (pos.line, pos.column));
+
+        let last_line = labels.iter().map(|(pos, _)| pos.line + first_line).max();
+        let width = last_line.unwrap_or(0).to_string().len();
+        let gutter = " ".repeat(width);
+
+        if !labels.is_empty() {
+            out.push(format!("{} |", gutter));
+        }
+        let mut shown_line = None;
+        for (pos, label) in labels {
+            if shown_line != Some(pos.line) {
+                let text = source.lines().nth(pos.line).unwrap_or("");
+                // Tabs are shown as single spaces so the underlines line up
+                let text = text.trim_end_matches('\r').replace('\t', " ");
+                out.push(format!(
+                    "{:>width$} | {}",
+                    pos.line + first_line,
+                    text,
+                    width = width
+                ));
+                shown_line = Some(pos.line);
+            }
+            let underline = format!(
+                "{} | {}{} {}",
+                gutter,
+                " ".repeat(pos.column),
+                "^".repeat(pos.length),
+                label.message
+            );
+            out.push(underline.trim_end().to_owned());
+        }
+
+        for note in &self.notes {
+            out.push(format!("{} = note: {}", gutter, note));
+        }
+        for help in &self.help {
+            out.push(format!("{} = help: {}", gutter, help));
+        }
+        out
+    }
+}
+
+/// Where a label is within the source, with columns and lengths in characters
+struct LabelPosition {
+    line: usize,
+    column: usize,
+    /// How many characters to underline, at least one so that empty spans
+    /// such as the end of the input are still visible
+    length: usize,
+}
+
+impl LabelPosition {
+    fn new(source: &str, label: &Label) -> LabelPosition {
+        let (line, column) = line_col(source, label.start);
+        let start = floor_char_boundary(source, label.start);
+        let line_end = source[start..]
+            .find('\n')
+            .map_or(source.len(), |i| start + i);
+        let end = floor_char_boundary(source, label.end.max(start).min(line_end));
+        LabelPosition {
+            line,
+            column,
+            length: source[start..end].chars().count().max(1),
+        }
+    }
+}
@@ -1,17 +1,146 @@
-use lalrpop_util::ParseError::UnrecognizedToken;
+use lalrpop_util::ParseError;
 
-pub fn try_humanize(error: &crate::ast::TErrorRecovery) -> Option<String> {
-    if let UnrecognizedToken { token: None, .. } = error.error {
-        return Some("Unexpected end of input".to_owned());
+use crate::ast::{self, precedence::PREFIX_OPERATORS, TParseError};
+use crate::diagnostic::Diagnostic;
+use crate::eval::{EvalError, SpannedEvalError};
+use crate::token::SpannedError;
+
+/// A friendly name for a terminal in the grammar, which lalrpop reports quoted
+/// like `"\"int literal\""`
+fn terminal_name(terminal: &str) -> String {
+    match terminal.trim_matches('"') {
+        "int literal" | "float literal" | "imaginary literal" => "a number".to_owned(),
+        "str literal" => "a string".to_owned(),
+        "ident" => "an identifier".to_owned(),
+        "operator" => "an operator".to_owned(),
+        other => format!("`{}`", other),
+    }
+}
+
+/// Lists the expected terminals like "a number, an identifier or `(`"
+fn expected_list(expected: &[String]) -> String {
+    let mut names: Vec<String> = Vec::new();
+    for name in expected.iter().map(|terminal| terminal_name(terminal)) {
+        if !names.contains(&name) {
+            names.push(name);
+        }
+    }
+    match names.split_last() {
+        None => "nothing".to_owned(),
+        Some((last, rest)) if rest.is_empty() => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+/// Lists items in backticks like "`a`, `b` or `c`"
+fn code_list(items: &[&str]) -> String {
+    let items: Vec<_> = items.iter().map(|item| format!("`{}`", item)).collect();
+    match items.split_last() {
+        None => String::new(),
+        Some((last, rest)) if rest.is_empty() => last.clone(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+    }
+}
+
+pub fn parse_diagnostic(error: &TParseError, input: &str) -> Diagnostic {
+    match error {
+        ParseError::InvalidToken { location } => {
+            Diagnostic::new("Invalid token").label(*location, *location, "not understood")
+        }
+        ParseError::UnrecognizedToken {
+            token: None,
+            expected,
+        } => Diagnostic::new("Unexpected end of input").label(
+            input.len(),
+            input.len(),
+            format!("expected {}", expected_list(expected)),
+        ),
+        ParseError::UnrecognizedToken {
+            token: Some((start, token, end)),
+            expected,
+        } => {
+            let diagnostic = Diagnostic::new(format!("Unexpected {}", token.describe())).label(
+                *start,
+                *end,
+                format!("expected {}", expected_list(expected)),
+            );
+            if expected.iter().any(|t| t.trim_matches('"') == "operator") {
+                diagnostic.help("there may be an operator missing before this")
+            } else {
+                diagnostic
+            }
+        }
+        ParseError::ExtraToken {
+            token: (start, token, end),
+        } => Diagnostic::new(format!("Unexpected {}", token.describe()))
+            .label(*start, *end, "expected the input to end before this")
+            .help("separate statements with `;`"),
+        ParseError::User {
+            error: ast::Error::InvalidPrefixOperator { op, start, end },
+        } => Diagnostic::new(format!("`{}` cannot be used as a prefix operator", op))
+            .label(*start, *end, "not a prefix operator")
+            .note(format!(
+                "the prefix operators are {}",
+                code_list(PREFIX_OPERATORS)
+            )),
+    }
+}
+
+pub fn lex_diagnostic(error: &SpannedError) -> Diagnostic {
+    use crate::token::Error::*;
+    let SpannedError(error, span) = error;
+    let label = match error {
+        PestErr(_) | UnexpectedChar(_) => "not understood",
+        InvalidInteger => "not a valid integer",
+        InvalidHex => "not valid hex",
+        InvalidBinary => "not valid binary",
+        InvalidFloat => "not a valid float",
+        UnclosedString => "missing a closing `\"`",
+        InvalidEscape(_) => "not a valid escape",
+        UnknownKeyword | UnknownGrouping | UnknownSymbol(_) | UnknownErr => "",
+    };
+    let diagnostic = Diagnostic::new(error.to_string()).label(span.start, span.end, label);
+    match error {
+        InvalidEscape(_) => {
+            diagnostic.note(r#"the escapes are `\"`, `\\`, `\n`, `\t`, `\r`, `\0` and `\u{...}`"#)
+        }
+        _ => diagnostic,
     }
-    if let UnrecognizedToken {
-        token: Some(_),
-        ref expected,
-    } = error.error
-    {
-        if expected.contains(&"\"operator\"".to_owned()) {
-            return Some("Possible missing operator".to_owned());
+}
+
+pub fn eval_diagnostic(error: &SpannedEvalError) -> Diagnostic {
+    let (start, end) = (error.span.start, error.span.end);
+    let mut diagnostic = Diagnostic::new(error.error.to_string());
+    diagnostic = match error.trace.last() {
+        Some(frame) => {
+            diagnostic.label(start, end, format!("in this call to `{}`", frame.function))
+        }
+        None => diagnostic.label(start, end, ""),
+    };
+    for frame in &error.trace {
+        diagnostic = diagnostic.note(format!("in `{}` at `{}`", frame.function, frame.expr));
+    }
+    match &error.error {
+        EvalError::UnknownIdentifier {
+            suggestion: Some(suggestion),
+            ..
+        }
+        | EvalError::UnknownFunction {
+            suggestion: Some(suggestion),
+            ..
+        } => diagnostic.help(format!("did you mean `{}`?", suggestion)),
+        EvalError::UnknownIdentifier { name, .. } => {
+            diagnostic.help(format!("define it with `let {} = ...`", name))
+        }
+        EvalError::UnknownFunction { name, .. } => {
+            diagnostic.help(format!("define it with `fn {}<...>{{...}}`", name))
         }
+        EvalError::NotCallable(_) => diagnostic.note("only functions can be called"),
+        EvalError::InvalidPrecision(_) => diagnostic.note(format!(
+            "the precision must be between {} and {} bits",
+            rug::float::prec_min(),
+            rug::float::prec_max()
+        )),
+        _ => diagnostic,
     }
-    None
 }
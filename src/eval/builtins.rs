@@ -0,0 +1,199 @@
+use rug::float::Constant;
+use rug::Float;
+
+use crate::ast::Number;
+use crate::token::{self, SpannedToken, Token};
+
+use super::{EvalError, Value};
+
+/// Every builtin function along with the number of arguments it takes
+pub const BUILTINS: &[(&str, usize)] = &[
+    ("sin", 1),
+    ("cos", 1),
+    ("tan", 1),
+    ("asin", 1),
+    ("acos", 1),
+    ("atan", 1),
+    ("csc", 1),
+    ("sec", 1),
+    ("cot", 1),
+    ("sqrt", 1),
+    ("re", 1),
+    ("im", 1),
+    ("conj", 1),
+    ("arg", 1),
+    ("abs", 1),
+    ("len", 1),
+    ("upper", 1),
+    ("lower", 1),
+    ("substr", 3),
+    ("split", 2),
+    ("contains", 2),
+    ("repeat", 2),
+    ("num", 1),
+    ("str", 1),
+];
+
+/// Names of the builtin constants, which are computed to the current precision
+pub const CONSTANTS: &[&str] = &["pi", "e", "i", "true", "false"];
+
+/// The longest string in bytes that `repeat` will build, anything longer is
+/// more likely a mistake than something that fits in memory
+pub const MAX_REPEAT_LEN: usize = 1 << 30;
+
+pub fn constant(name: &str, prec: u32) -> Option<Value> {
+    Some(match name {
+        "pi" => Number::Float(Float::with_val(prec, Constant::Pi)).into(),
+        "e" => Number::Float(Float::with_val(prec, 1).exp()).into(),
+        "i" => Number::imaginary(Float::with_val(prec, 1)).into(),
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => return None,
+    })
+}
+
+pub fn arity(name: &str) -> Option<usize> {
+    BUILTINS
+        .iter()
+        .find(|(builtin, _)| *builtin == name)
+        .map(|(_, arity)| *arity)
+}
+
+/// Calls a builtin function, the number of arguments must already be checked
+///
+/// `prec` is used for any floats created from exact numbers, and strings built
+/// by `repeat` may be at most `max_repeat_len` bytes
+pub fn call(
+    name: String,
+    args: Vec<Value>,
+    prec: u32,
+    max_repeat_len: usize,
+) -> Result<Value, EvalError> {
+    let mut args = args.into_iter();
+    let mut next = || args.next().unwrap_or(Value::Unit);
+    Ok(match name.as_str() {
+        "sin" => number(&name, next())?.sin(prec).into(),
+        "cos" => number(&name, next())?.cos(prec).into(),
+        "tan" => number(&name, next())?.tan(prec).into(),
+        "asin" => number(&name, next())?.asin(prec).into(),
+        "acos" => number(&name, next())?.acos(prec).into(),
+        "atan" => number(&name, next())?.atan(prec).into(),
+        "csc" => number(&name, next())?.csc(prec).into(),
+        "sec" => number(&name, next())?.sec(prec).into(),
+        "cot" => number(&name, next())?.cot(prec).into(),
+        "sqrt" => number(&name, next())?.sqrt(prec).into(),
+        "re" => number(&name, next())?.re().into(),
+        "im" => number(&name, next())?.im().into(),
+        "conj" => number(&name, next())?.conj().into(),
+        "arg" => number(&name, next())?.arg(prec).into(),
+        "abs" => number(&name, next())?.abs().into(),
+        "len" => {
+            let len = match next() {
+                Value::String(s) => s.chars().count(),
+                Value::List(l) => l.len(),
+                arg => return Err(invalid_argument(&name, "string or list", &arg)),
+            };
+            Number::Int(len.into()).into()
+        }
+        "upper" => string(&name, next())?.to_uppercase().into(),
+        "lower" => string(&name, next())?.to_lowercase().into(),
+        "substr" => {
+            let s = string(&name, next())?;
+            let start = index(&name, next())?;
+            let len = index(&name, next())?;
+            s.chars().skip(start).take(len).collect::<String>().into()
+        }
+        "split" => {
+            let s = string(&name, next())?;
+            let sep = string(&name, next())?;
+            let parts: Vec<Value> = if sep.is_empty() {
+                s.chars().map(|c| c.to_string().into()).collect()
+            } else {
+                s.split(sep.as_str()).map(|p| p.to_owned().into()).collect()
+            };
+            parts.into()
+        }
+        "contains" => {
+            let s = string(&name, next())?;
+            let pattern = string(&name, next())?;
+            s.contains(pattern.as_str()).into()
+        }
+        "repeat" => {
+            let s = string(&name, next())?;
+            let count = index(&name, next())?;
+            match s.len().checked_mul(count) {
+                Some(len) if len <= max_repeat_len => s.repeat(count).into(),
+                _ => return Err(EvalError::TooLarge(name)),
+            }
+        }
+        "num" => {
+            let s = string(&name, next())?;
+            parse_number(&s, prec)
+                .ok_or_else(|| EvalError::InvalidNumber(s))?
+                .into()
+        }
+        "str" => match next() {
+            Value::String(s) => s.into(),
+            arg => arg.to_string().into(),
+        },
+        _ => {
+            return Err(EvalError::UnknownFunction {
+                name,
+                suggestion: None,
+            })
+        }
+    })
+}
+
+/// Parses a number using the same syntax as number literals, with an optional sign
+fn parse_number(input: &str, prec: u32) -> Option<Number> {
+    let input = input.trim();
+    let (negative, digits) = match input.chars().next() {
+        Some('-') => (true, &input[1..]),
+        Some('+') => (false, &input[1..]),
+        _ => (false, input),
+    };
+    let num = match token::tokenize_with_precision(digits, prec)
+        .ok()?
+        .as_slice()
+    {
+        [SpannedToken(Token::Integer(i), _)] => Number::Int(i.clone()),
+        [SpannedToken(Token::Float(f), _)] => Number::Float(f.clone()),
+        [SpannedToken(Token::Imaginary(f), _)] => Number::imaginary(f.clone()),
+        _ => return None,
+    };
+    Some(if negative { -num } else { num })
+}
+
+fn invalid_argument(name: &str, expected: &'static str, found: &Value) -> EvalError {
+    EvalError::InvalidArgument {
+        name: name.to_owned(),
+        expected,
+        found: found.type_name(),
+    }
+}
+
+fn number(name: &str, arg: Value) -> Result<Number, EvalError> {
+    match arg {
+        Value::Number(n) => Ok(n),
+        arg => Err(invalid_argument(name, "number", &arg)),
+    }
+}
+
+fn string(name: &str, arg: Value) -> Result<String, EvalError> {
+    match arg {
+        Value::String(s) => Ok(s),
+        arg => Err(invalid_argument(name, "string", &arg)),
+    }
+}
+
+/// A non-negative integer, such as a position or count
+fn index(name: &str, arg: Value) -> Result<usize, EvalError> {
+    match arg {
+        Value::Number(Number::Int(ref i)) => match i.to_usize() {
+            Some(i) => Ok(i),
+            None => Err(invalid_argument(name, "non-negative integer", &arg)),
+        },
+        arg => Err(invalid_argument(name, "non-negative integer", &arg)),
+    }
+}
@@ -0,0 +1,149 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::ast::Recall;
+use crate::token::Span;
+
+/// Errors that can occur while evaluating an ast
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    UnknownIdentifier {
+        name: String,
+        /// A defined name that is close enough to be what was meant
+        suggestion: Option<String>,
+    },
+    UnknownFunction {
+        name: String,
+        suggestion: Option<String>,
+    },
+    ArityMismatch {
+        name: String,
+        expected: usize,
+        found: usize,
+    },
+    UnsupportedOperator(String),
+    InvalidOperands {
+        op: String,
+        lhs: &'static str,
+        rhs: &'static str,
+    },
+    InvalidOperand {
+        op: String,
+        operand: &'static str,
+    },
+    /// A call to a variable that does not hold a function
+    NotCallable(String),
+    InvalidArgument {
+        name: String,
+        expected: &'static str,
+        found: &'static str,
+    },
+    /// A string passed to `num` that is not a valid number
+    InvalidNumber(String),
+    /// A builtin whose result would not fit in memory
+    TooLarge(String),
+    ShellFailure {
+        cmd: String,
+        reason: String,
+    },
+    DivisionByZero,
+    /// A `#` referring to a result that does not exist
+    NoResult(Recall),
+    /// A shell call evaluated in a snapshot, which must not have side effects
+    ShellDisabled(String),
+    /// A float precision outside of the range supported by MPFR
+    InvalidPrecision(u32),
+    /// The parser recovered from an error and left a placeholder node
+    InvalidNode,
+    /// A snapshot did more work than it is allowed to
+    LimitExceeded,
+}
+
+impl Display for EvalError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        use self::EvalError::*;
+        match self {
+            UnknownIdentifier { name, .. } => write!(f, "Unknown identifier `{}`", name),
+            UnknownFunction { name, .. } => write!(f, "Unknown function `{}`", name),
+            ArityMismatch {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` takes {} argument{} but {} {} given",
+                name,
+                expected,
+                if *expected == 1 { "" } else { "s" },
+                found,
+                if *found == 1 { "was" } else { "were" }
+            ),
+            UnsupportedOperator(op) => write!(f, "Unsupported operator `{}`", op),
+            InvalidOperands { op, lhs, rhs } => {
+                write!(f, "Cannot apply `{}` to a {} and a {}", op, lhs, rhs)
+            }
+            InvalidOperand { op, operand } => write!(f, "Cannot apply `{}` to a {}", op, operand),
+            NotCallable(name) => write!(f, "`{}` is not a function", name),
+            InvalidArgument {
+                name,
+                expected,
+                found,
+            } => write!(
+                f,
+                "`{}` expects a {} but was given a {}",
+                name, expected, found
+            ),
+            InvalidNumber(s) => write!(f, "Cannot parse {:?} as a number", s),
+            TooLarge(name) => write!(f, "The result of `{}` is too large", name),
+            ShellFailure { cmd, reason } => write!(f, "Shell command `{}` failed: {}", cmd, reason),
+            DivisionByZero => write!(f, "Division by zero"),
+            NoResult(Recall::Absolute(n)) => write!(f, "There is no result #{}", n),
+            NoResult(Recall::Relative(n)) => write!(f, "There is no result #-{}", n),
+            ShellDisabled(cmd) => write!(f, "Shell command `{}` was not run", cmd),
+            InvalidPrecision(prec) => write!(f, "Invalid precision of {} bits", prec),
+            InvalidNode => write!(f, "Cannot evaluate an invalid expression"),
+            LimitExceeded => write!(f, "Gave up evaluating a preview"),
+        }
+    }
+}
+
+impl std::error::Error for EvalError {}
+
+/// An error along with where it happened
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpannedEvalError {
+    pub error: EvalError,
+    /// The sub-expression that failed, or the call to the user defined
+    /// function that it failed in
+    pub span: Span,
+    /// The user defined functions that the error happened in, innermost first
+    pub trace: Vec<Frame>,
+}
+
+/// A call to a user defined function in the trace of an error
+#[derive(Debug, Clone, PartialEq)]
+pub struct Frame {
+    pub function: String,
+    /// The sub-expression of the function's body that failed, within the
+    /// source the function was defined in
+    pub span: Span,
+    /// The failing sub-expression written as source
+    pub expr: String,
+}
+
+impl EvalError {
+    pub fn at(self, span: Span) -> SpannedEvalError {
+        SpannedEvalError {
+            error: self,
+            span,
+            trace: vec![],
+        }
+    }
+}
+
+impl Display for SpannedEvalError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        self.error.fmt(f)
+    }
+}
+
+impl std::error::Error for SpannedEvalError {}
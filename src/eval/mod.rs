@@ -0,0 +1,503 @@
+mod builtins;
+mod error;
+mod suggest;
+mod value;
+mod workspace;
+
+pub use self::error::{EvalError, Frame, SpannedEvalError};
+pub use self::value::{Function, RationalStyle, Value};
+pub use self::workspace::{LoadMode, WorkspaceError};
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use rug::Integer;
+
+use crate::ast::{Number, Recall, SpannedNode, DEFAULT_PRECISION};
+use crate::token::Span;
+
+/// The number of nodes a snapshot may evaluate before giving up
+const SNAPSHOT_STEPS: usize = 10_000;
+/// How deeply a snapshot may nest calls to user defined functions
+const SNAPSHOT_DEPTH: usize = 64;
+/// The most bits in an integer or rational built by `**` or a shift in a snapshot
+const SNAPSHOT_INT_BITS: u64 = 1 << 16;
+/// The longest string in bytes that `repeat` builds in a snapshot
+const SNAPSHOT_REPEAT_LEN: usize = 1 << 16;
+
+#[derive(Clone)]
+pub struct EvalContext {
+    /// How rationals in results should be displayed
+    pub rational_style: RationalStyle,
+    /// Precision, in bits, of floats created from exact numbers and of constants
+    precision: u32,
+    values: HashMap<String, Value>,
+    functions: HashMap<String, Function>,
+    local_values: Vec<HashMap<String, Value>>,
+    /// Every result so far, recalled with `#`, shared with snapshots so that
+    /// taking one does not copy them
+    results: Rc<Vec<Value>>,
+    /// Results evaluated in a snapshot, which follow the shared results
+    snapshot_results: Vec<Value>,
+    /// Whether shell calls are run, disabled for snapshots
+    allow_shell: bool,
+    /// The nodes a snapshot may still evaluate, `None` if there is no limit
+    steps_left: Option<usize>,
+}
+
+impl EvalContext {
+    pub fn new() -> EvalContext {
+        let functions = builtins::BUILTINS
+            .iter()
+            .map(|(name, _)| (name.to_string(), Function::Builtin(name.to_string())))
+            .collect();
+        EvalContext {
+            rational_style: RationalStyle::Fraction,
+            precision: DEFAULT_PRECISION,
+            values: HashMap::new(),
+            functions,
+            local_values: vec![],
+            results: Rc::new(vec![]),
+            snapshot_results: vec![],
+            allow_shell: true,
+            steps_left: None,
+        }
+    }
+
+    /// A copy of the context that can be evaluated in without side effects
+    ///
+    /// Variables and functions defined in the snapshot do not affect this
+    /// context, and shell calls fail instead of running. Evaluation in the
+    /// snapshot is limited so that it gives up with `LimitExceeded` rather than
+    /// recursing without end or building enormous integers
+    pub fn snapshot(&self) -> EvalContext {
+        EvalContext {
+            allow_shell: false,
+            steps_left: Some(SNAPSHOT_STEPS),
+            ..self.clone()
+        }
+    }
+
+    pub fn precision(&self) -> u32 {
+        self.precision
+    }
+
+    /// Sets the precision, in bits, used for new floats and constants
+    pub fn set_precision(&mut self, precision: u32) -> Result<(), EvalError> {
+        if precision < rug::float::prec_min() || precision > rug::float::prec_max() {
+            return Err(EvalError::InvalidPrecision(precision));
+        }
+        self.precision = precision;
+        Ok(())
+    }
+
+    /// Removes a global variable or user defined function, returning whether
+    /// anything was removed
+    ///
+    /// A builtin function shadowed by the removed function is restored
+    pub fn remove(&mut self, name: &str) -> bool {
+        let removed_value = self.values.remove(name).is_some();
+        let removed_function = match self.functions.get(name) {
+            Some(Function::UserDefined { .. }) => {
+                self.functions.remove(name);
+                if builtins::arity(name).is_some() {
+                    let builtin = Function::Builtin(name.to_owned());
+                    self.functions.insert(name.to_owned(), builtin);
+                }
+                true
+            }
+            _ => false,
+        };
+        removed_value || removed_function
+    }
+
+    /// Removes all user defined variables and functions along with previous
+    /// results, keeping settings such as the precision
+    pub fn reset(&mut self) {
+        *self = EvalContext {
+            rational_style: self.rational_style,
+            precision: self.precision,
+            allow_shell: self.allow_shell,
+            ..EvalContext::new()
+        };
+    }
+
+    /// Forgets every result so far, so the next is recalled with `#1`
+    pub fn clear_results(&mut self) {
+        self.results = Rc::new(vec![]);
+    }
+
+    /// Every result so far, the first of which is recalled with `#1`
+    ///
+    /// Results evaluated in a snapshot are not included
+    pub fn results(&self) -> &[Value] {
+        &self.results
+    }
+
+    /// User defined global variables
+    pub fn values(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.values
+            .iter()
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// Both builtin and user defined functions
+    pub fn functions(&self) -> impl Iterator<Item = (&str, &Function)> {
+        self.functions
+            .iter()
+            .map(|(name, func)| (name.as_str(), func))
+    }
+
+    /// Names of the builtin constants such as `pi`
+    pub fn constants(&self) -> &'static [&'static str] {
+        builtins::CONSTANTS
+    }
+
+    /// Whether a name refers to a global variable, constant or function
+    pub fn is_defined(&self, name: &str) -> bool {
+        self.values.contains_key(name)
+            || self.functions.contains_key(name)
+            || builtins::CONSTANTS.contains(&name)
+    }
+
+    fn eval_op(&self, lhs: Value, op: String, rhs: Value) -> Result<Value, EvalError> {
+        let (lhs, rhs) = match (lhs, rhs) {
+            (Value::Number(lhs), Value::Number(rhs)) => {
+                if self.steps_left.is_some() && builds_large_int(&lhs, &op, &rhs) {
+                    return Err(EvalError::LimitExceeded);
+                }
+                (lhs, rhs)
+            }
+            (Value::String(lhs), Value::String(rhs)) if op == "+" => {
+                return Ok(Value::String(lhs + &rhs))
+            }
+            (lhs, rhs) => {
+                return Err(EvalError::InvalidOperands {
+                    op,
+                    lhs: lhs.type_name(),
+                    rhs: rhs.type_name(),
+                })
+            }
+        };
+        Ok(match op.as_str() {
+            "+" => lhs + rhs,
+            "-" => lhs - rhs,
+            "*" => lhs * rhs,
+            "/" if rhs.is_zero() => return Err(EvalError::DivisionByZero),
+            "/" => lhs / rhs,
+            "%" if lhs.is_complex() || rhs.is_complex() => {
+                return Err(EvalError::InvalidOperands {
+                    op,
+                    lhs: lhs.type_name(),
+                    rhs: rhs.type_name(),
+                })
+            }
+            "%" if rhs.is_zero() => return Err(EvalError::DivisionByZero),
+            "%" => lhs % rhs,
+            "**" if lhs.is_zero() && rhs.is_negative() => return Err(EvalError::DivisionByZero),
+            "**" => lhs.pow_with_prec(rhs, self.precision),
+            "<<" => lhs << rhs,
+            ">>" => lhs >> rhs,
+            _ => return Err(EvalError::UnsupportedOperator(op)),
+        }
+        .into())
+    }
+
+    fn eval_prefix(&self, op: String, rhs: Value) -> Result<Value, EvalError> {
+        let rhs = match rhs {
+            Value::Number(rhs) if op == "!" => {
+                return Ok(Number::Int(u8::from(rhs.is_zero()).into()).into())
+            }
+            rhs if op == "!" => return Ok(Value::Bool(!rhs.is_truthy())),
+            Value::Number(rhs) => rhs,
+            rhs => {
+                return Err(EvalError::InvalidOperand {
+                    op,
+                    operand: rhs.type_name(),
+                })
+            }
+        };
+        Ok(match op.as_str() {
+            "-" => -rhs,
+            "+" => rhs,
+            "~" => !rhs,
+            _ => return Err(EvalError::UnsupportedOperator(op)),
+        }
+        .into())
+    }
+
+    /// Finds a variable, searching the innermost function scope before globals
+    fn lookup_value(&self, key: &str) -> Option<&Value> {
+        self.local_values
+            .iter()
+            .rev()
+            .filter_map(|local| local.get(key))
+            .chain(self.values.get(key))
+            .next()
+    }
+
+    fn lookup_ident(&self, key: &str) -> Result<Value, EvalError> {
+        if let Some(val) = self.lookup_value(key) {
+            return Ok(val.clone());
+        }
+        if let Some(val) = builtins::constant(key, self.precision) {
+            return Ok(val);
+        }
+        self.functions
+            .get(key)
+            .map(|func| Value::Function(func.clone()))
+            .ok_or_else(|| EvalError::UnknownIdentifier {
+                name: key.to_owned(),
+                suggestion: self.suggest_ident(key),
+            })
+    }
+
+    /// Finds the function a call refers to, variables holding functions take
+    /// priority over named functions
+    fn lookup_function(&self, name: &str) -> Result<Function, EvalError> {
+        match (self.lookup_value(name), self.functions.get(name)) {
+            (Some(Value::Function(func)), _) => Ok(func.clone()),
+            (_, Some(func)) => Ok(func.clone()),
+            (Some(_), None) => Err(EvalError::NotCallable(name.to_owned())),
+            (None, None) => Err(EvalError::UnknownFunction {
+                name: name.to_owned(),
+                suggestion: self.suggest_function(name),
+            }),
+        }
+    }
+
+    /// Every variable in scope, including those of outer function scopes
+    fn visible_values(&self) -> impl Iterator<Item = (&str, &Value)> {
+        self.local_values
+            .iter()
+            .flat_map(|local| local.iter())
+            .chain(self.values.iter())
+            .map(|(name, value)| (name.as_str(), value))
+    }
+
+    /// The defined name closest to an unknown identifier
+    fn suggest_ident(&self, name: &str) -> Option<String> {
+        let candidates = self
+            .visible_values()
+            .map(|(name, _)| name)
+            .chain(builtins::CONSTANTS.iter().cloned())
+            .chain(self.functions.keys().map(String::as_str));
+        suggest::closest(name, candidates).map(str::to_owned)
+    }
+
+    /// The function, or variable holding one, closest to an unknown function
+    fn suggest_function(&self, name: &str) -> Option<String> {
+        let candidates = self
+            .visible_values()
+            .filter_map(|(name, value)| match value {
+                Value::Function(_) => Some(name),
+                _ => None,
+            })
+            .chain(self.functions.keys().map(String::as_str));
+        suggest::closest(name, candidates).map(str::to_owned)
+    }
+
+    fn eval_function(
+        &mut self,
+        name: &str,
+        args: Vec<SpannedNode>,
+        span: Span,
+    ) -> Result<Value, SpannedEvalError> {
+        let func = self.lookup_function(name).map_err(|e| e.at(span))?;
+        let mut arg_values = Vec::with_capacity(args.len());
+        for arg in args {
+            arg_values.push(self.eval_internal(arg)?);
+        }
+        self.call_function(func, arg_values, span)
+    }
+
+    /// Calls a function, errors point at the call's span
+    fn call_function(
+        &mut self,
+        func: Function,
+        args: Vec<Value>,
+        span: Span,
+    ) -> Result<Value, SpannedEvalError> {
+        match func {
+            Function::Builtin(name) => {
+                let expected = builtins::arity(&name).unwrap_or(0);
+                if args.len() != expected {
+                    return Err(EvalError::ArityMismatch {
+                        name,
+                        expected,
+                        found: args.len(),
+                    }
+                    .at(span));
+                }
+                let max_repeat_len = match self.steps_left {
+                    Some(_) => SNAPSHOT_REPEAT_LEN,
+                    None => builtins::MAX_REPEAT_LEN,
+                };
+                builtins::call(name, args, self.precision, max_repeat_len).map_err(|e| e.at(span))
+            }
+            Function::UserDefined { name, params, body } => {
+                if args.len() != params.len() {
+                    return Err(EvalError::ArityMismatch {
+                        name,
+                        expected: params.len(),
+                        found: args.len(),
+                    }
+                    .at(span));
+                }
+                if self.steps_left.is_some() && self.local_values.len() >= SNAPSHOT_DEPTH {
+                    return Err(EvalError::LimitExceeded.at(span));
+                }
+                let new_local = params.into_iter().zip(args).collect();
+                self.local_values.push(new_local);
+                let ret = self.eval_body(&body);
+                self.local_values.pop();
+
+                // Record where in the body the error happened before pointing at the call
+                ret.map_err(|mut e| {
+                    let expr = body
+                        .iter()
+                        .find_map(|node| node.find(e.span))
+                        .map_or_else(String::new, |node| node.to_string());
+                    e.trace.push(Frame {
+                        function: name,
+                        span: e.span,
+                        expr,
+                    });
+                    e.span = span;
+                    e
+                })
+            }
+        }
+    }
+
+    /// Evaluates each node in a function body, returning the value of the last one
+    fn eval_body(&mut self, body: &[SpannedNode]) -> Result<Value, SpannedEvalError> {
+        let mut ret = Value::Unit;
+        for node in body {
+            ret = self.eval_internal(node.clone())?;
+        }
+        Ok(ret)
+    }
+
+    fn call_shell_func(&mut self, cmd: &str) -> Result<Value, EvalError> {
+        use std::process::Command;
+        if !self.allow_shell {
+            return Err(EvalError::ShellDisabled(cmd.to_owned()));
+        }
+        let status = if cfg!(target_os = "windows") {
+            Command::new("cmd").arg("/C").arg(cmd).status()
+        } else {
+            Command::new("sh").arg("-c").arg(cmd).status()
+        };
+        let shell_failure = |reason: String| EvalError::ShellFailure {
+            cmd: cmd.to_owned(),
+            reason,
+        };
+        match status {
+            Ok(status) => match status.code() {
+                Some(code) => Ok(Number::Int(code.into()).into()),
+                None => Err(shell_failure("terminated by a signal".to_owned())),
+            },
+            Err(e) => Err(shell_failure(e.to_string())),
+        }
+    }
+
+    /// Finds a previous result, `#` is zero before there are any results
+    fn recall(&self, recall: Recall) -> Result<Value, EvalError> {
+        let count = self.results.len() + self.snapshot_results.len();
+        let index = match recall {
+            Recall::Relative(1) if count == 0 => return Ok(Number::default().into()),
+            Recall::Absolute(n) => n.checked_sub(1),
+            Recall::Relative(n) => count.checked_sub(n),
+        };
+        index
+            .and_then(|i| {
+                self.results.get(i).or_else(|| {
+                    let i = i.checked_sub(self.results.len())?;
+                    self.snapshot_results.get(i)
+                })
+            })
+            .cloned()
+            .ok_or(EvalError::NoResult(recall))
+    }
+
+    fn eval_internal(&mut self, node: SpannedNode) -> Result<Value, SpannedEvalError> {
+        use crate::ast::Node::*;
+        let span = node.span;
+        if let Some(steps_left) = &mut self.steps_left {
+            if *steps_left == 0 {
+                return Err(EvalError::LimitExceeded.at(span));
+            }
+            *steps_left -= 1;
+        }
+        match node.node {
+            Prev(recall) => self.recall(recall).map_err(|e| e.at(span)),
+            Ident(key) => self.lookup_ident(&key).map_err(|e| e.at(span)),
+            Number(num) => Ok(num.into()),
+            String(str) => Ok(Value::String(str)),
+            Infix { lhs, op, rhs } => {
+                let lhs = self.eval_internal(*lhs)?;
+                let rhs = self.eval_internal(*rhs)?;
+                self.eval_op(lhs, op, rhs).map_err(|e| e.at(span))
+            }
+            Prefix { op, rhs } => {
+                let rhs = self.eval_internal(*rhs)?;
+                self.eval_prefix(op, rhs).map_err(|e| e.at(span))
+            }
+            Let(key, node) => {
+                let value = self.eval_internal(*node)?;
+                self.values.insert(key, value.clone());
+                Ok(value)
+            }
+            FunctionDef { name, params, body } => {
+                let func = Function::UserDefined {
+                    name: name.clone(),
+                    params,
+                    body,
+                };
+                self.functions.insert(name, func.clone());
+                Ok(func.into())
+            }
+            FunctionCall { name, args } => self.eval_function(&name, args, span),
+            ShellCall { cmd } => self.call_shell_func(&cmd).map_err(|e| e.at(span)),
+            Error => Err(EvalError::InvalidNode.at(span)),
+        }
+    }
+
+    /// Evaluates a node, adding its value to the results
+    pub fn eval(&mut self, node: SpannedNode) -> Result<&Value, SpannedEvalError> {
+        let result = self.eval_internal(node)?;
+        let results = match self.steps_left {
+            Some(_) => &mut self.snapshot_results,
+            None => Rc::make_mut(&mut self.results),
+        };
+        results.push(result);
+        Ok(results.last().unwrap()) // Safe, we just pushed the value
+    }
+}
+
+/// Whether raising to a power or shifting an integer or rational would build
+/// one too large to evaluate in a snapshot
+fn builds_large_int(lhs: &Number, op: &str, rhs: &Number) -> bool {
+    let lhs_bits = match lhs {
+        Number::Int(lhs) => u64::from(lhs.significant_bits()),
+        Number::Rational(lhs) => {
+            u64::from(lhs.numer().significant_bits()) + u64::from(lhs.denom().significant_bits())
+        }
+        _ => return false,
+    };
+    let bits = match (op, rhs) {
+        ("**", Number::Int(rhs)) => match Integer::from(rhs.abs_ref()).to_u64() {
+            Some(exponent) => lhs_bits.saturating_mul(exponent),
+            None => return true,
+        },
+        // Shifting left by a negative amount, or right by any, does not grow
+        ("<<", rhs) if !rhs.is_negative() => {
+            let amount = rhs.clone().into_float(DEFAULT_PRECISION).to_f64();
+            lhs_bits.saturating_add(amount as u64)
+        }
+        // Other exponents give floats
+        _ => return false,
+    };
+    bits > SNAPSHOT_INT_BITS
+}
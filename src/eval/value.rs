@@ -0,0 +1,147 @@
+use std::fmt::{self, Display, Formatter};
+
+use crate::ast::{Number, SpannedNode};
+
+/// A callable function, either provided by slide or defined by the user
+#[derive(Debug, Clone, PartialEq)]
+pub enum Function {
+    Builtin(String),
+    UserDefined {
+        name: String,
+        params: Vec<String>,
+        body: Vec<SpannedNode>,
+    },
+}
+
+impl Function {
+    pub fn name(&self) -> &str {
+        match self {
+            Function::Builtin(name) => name,
+            Function::UserDefined { name, .. } => name,
+        }
+    }
+}
+
+impl Display for Function {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Function::Builtin(name) => write!(f, "builtin {}", name),
+            Function::UserDefined { name, params, .. } => {
+                write!(f, "fn {}<{}>", name, params.join(", "))
+            }
+        }
+    }
+}
+
+/// How rational numbers are displayed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RationalStyle {
+    /// As an exact fraction, `a/b`
+    Fraction,
+    /// As an approximate decimal
+    Decimal,
+}
+
+/// The result of evaluating an expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Number(Number),
+    Bool(bool),
+    String(String),
+    List(Vec<Value>),
+    Function(Function),
+    /// The absence of a value, such as the result of an empty function body
+    Unit,
+}
+
+impl Value {
+    /// A human readable name for the type of this value, used in errors
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Number(n) => n.type_name(),
+            Value::Bool(_) => "boolean",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Function(_) => "function",
+            Value::Unit => "unit",
+        }
+    }
+
+    /// Formats the value for display, showing any rationals in the given style
+    ///
+    /// `prec` is the precision used when converting rationals to decimals
+    pub fn format(&self, style: RationalStyle, prec: u32) -> String {
+        match (self, style) {
+            (Value::Number(n), RationalStyle::Decimal) => n.to_decimal(prec).to_string(),
+            (Value::List(l), RationalStyle::Decimal) => {
+                let items: Vec<_> = l.iter().map(|item| item.format(style, prec)).collect();
+                format!("[{}]", items.join(", "))
+            }
+            (value, _) => value.to_string(),
+        }
+    }
+
+    /// Whether the value counts as true in a logical context
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Number(n) => !n.is_zero(),
+            Value::Bool(b) => *b,
+            Value::String(s) => !s.is_empty(),
+            Value::List(l) => !l.is_empty(),
+            Value::Function(_) => true,
+            Value::Unit => false,
+        }
+    }
+}
+
+impl From<Number> for Value {
+    fn from(x: Number) -> Value {
+        Value::Number(x)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(x: bool) -> Value {
+        Value::Bool(x)
+    }
+}
+
+impl From<String> for Value {
+    fn from(x: String) -> Value {
+        Value::String(x)
+    }
+}
+
+impl From<Vec<Value>> for Value {
+    fn from(x: Vec<Value>) -> Value {
+        Value::List(x)
+    }
+}
+
+impl From<Function> for Value {
+    fn from(x: Function) -> Value {
+        Value::Function(x)
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            Value::Number(n) => n.fmt(f),
+            Value::Bool(b) => b.fmt(f),
+            Value::String(s) => write!(f, "{:?}", s),
+            Value::List(l) => {
+                write!(f, "[")?;
+                for (i, item) in l.iter().enumerate() {
+                    if i != 0 {
+                        write!(f, ", ")?;
+                    }
+                    item.fmt(f)?;
+                }
+                write!(f, "]")
+            }
+            Value::Function(func) => func.fmt(f),
+            Value::Unit => write!(f, "()"),
+        }
+    }
+}
@@ -0,0 +1,188 @@
+use std::fmt::{self, Display, Formatter};
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use crate::ast::{self, Node, Number, SpannedNode};
+use crate::token;
+
+use super::{EvalContext, EvalError, Function, SpannedEvalError, Value};
+
+/// How loading a workspace treats the existing variables and functions
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LoadMode {
+    /// Keep them, unless the workspace defines something with the same name
+    Merge,
+    /// Remove them first
+    Replace,
+}
+
+#[derive(Debug)]
+pub enum WorkspaceError {
+    Io(io::Error),
+    /// A line that could not be tokenized or parsed
+    Syntax(usize),
+    /// A line that could not be evaluated
+    Eval(usize, EvalError),
+}
+
+impl Display for WorkspaceError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        match self {
+            WorkspaceError::Io(e) => e.fmt(f),
+            WorkspaceError::Syntax(line) => write!(f, "Syntax error on line {}", line),
+            WorkspaceError::Eval(line, e) => write!(f, "Error on line {}: {}", line, e),
+        }
+    }
+}
+
+impl std::error::Error for WorkspaceError {}
+
+impl From<io::Error> for WorkspaceError {
+    fn from(e: io::Error) -> WorkspaceError {
+        WorkspaceError::Io(e)
+    }
+}
+
+/// Source code for a value, `None` for values that cannot be written as source
+/// such as lists
+fn value_source(ctx: &EvalContext, value: &Value) -> Option<String> {
+    Some(match value {
+        Value::Number(n) => {
+            let is_finite = match n {
+                Number::Float(f) => f.is_finite(),
+                Number::Complex(c) => c.real().is_finite() && c.imag().is_finite(),
+                _ => true,
+            };
+            if !is_finite {
+                return None;
+            }
+            Node::Number(n.clone()).to_string()
+        }
+        Value::Bool(b) => b.to_string(),
+        Value::String(s) => format!("{:?}", s),
+        Value::Function(Function::Builtin(name)) => name.clone(),
+        // Refer to the function by name if that still means the same function
+        Value::Function(func) if ctx.functions.get(func.name()) == Some(func) => {
+            func.name().to_owned()
+        }
+        Value::Function(Function::UserDefined { name, params, body }) => Node::FunctionDef {
+            name: name.clone(),
+            params: params.clone(),
+            body: body.clone(),
+        }
+        .to_string(),
+        Value::List(_) | Value::Unit => return None,
+    })
+}
+
+impl EvalContext {
+    /// Writes the variables and user defined functions as source code, one per line
+    ///
+    /// Returns the source along with the names of any variables that were
+    /// skipped because their values cannot be written as source
+    pub fn to_source(&self) -> (String, Vec<String>) {
+        let mut source = String::new();
+        let mut functions: Vec<_> = self
+            .functions
+            .values()
+            .filter_map(|func| match func {
+                Function::UserDefined { name, params, body } => Some(Node::FunctionDef {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                }),
+                Function::Builtin(_) => None,
+            })
+            .map(|node| node.to_string())
+            .collect();
+        functions.sort();
+        for func in functions {
+            source.push_str(&func);
+            source.push('\n');
+        }
+
+        let mut names: Vec<_> = self.values.keys().collect();
+        names.sort();
+        let mut skipped = Vec::new();
+        for name in names {
+            match value_source(self, &self.values[name]) {
+                Some(value) => source.push_str(&format!("let {} = ({})\n", name, value)),
+                None => skipped.push(name.clone()),
+            }
+        }
+        (source, skipped)
+    }
+
+    /// Evaluates a line of a workspace
+    ///
+    /// A function written inline in a `let` is stored in the variable without
+    /// being defined by name, as it may be an older version of a function that
+    /// has since been redefined
+    fn load_node(&mut self, node: SpannedNode) -> Result<(), SpannedEvalError> {
+        if let Node::Let(key, value) = &node.node {
+            if let Node::FunctionDef { name, params, body } = &value.node {
+                let func = Function::UserDefined {
+                    name: name.clone(),
+                    params: params.clone(),
+                    body: body.clone(),
+                };
+                self.values.insert(key.clone(), func.into());
+                return Ok(());
+            }
+        }
+        self.eval_internal(node).map(|_| ())
+    }
+
+    /// Evaluates source written by `to_source`, leaving the context unchanged
+    /// if any line fails
+    ///
+    /// Shell calls in the source fail rather than running
+    pub fn load_source(&mut self, source: &str, mode: LoadMode) -> Result<(), WorkspaceError> {
+        let mut loaded = self.clone();
+        if mode == LoadMode::Replace {
+            loaded.reset();
+        }
+        loaded.allow_shell = false;
+        for (i, line) in source.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let tokens = token::tokenize_with_precision(line, loaded.precision)
+                .map_err(|_| WorkspaceError::Syntax(i + 1))?;
+            let lalr_tokens = tokens
+                .into_iter()
+                .map(|token| Ok((token.1.start, token.0, token.1.end)))
+                .collect();
+            let mut errors = Vec::new();
+            let nodes =
+                ast::parse(&mut errors, lalr_tokens).map_err(|_| WorkspaceError::Syntax(i + 1))?;
+            if !errors.is_empty() {
+                return Err(WorkspaceError::Syntax(i + 1));
+            }
+            for node in nodes {
+                loaded
+                    .load_node(node)
+                    .map_err(|e| WorkspaceError::Eval(i + 1, e.error))?;
+            }
+        }
+        loaded.allow_shell = self.allow_shell;
+        *self = loaded;
+        Ok(())
+    }
+
+    /// Saves the variables and user defined functions to a file
+    ///
+    /// Returns the names of any variables that were skipped, see `to_source`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<Vec<String>> {
+        let (source, skipped) = self.to_source();
+        fs::write(path, source)?;
+        Ok(skipped)
+    }
+
+    /// Loads variables and functions from a file written by `save`
+    pub fn load<P: AsRef<Path>>(&mut self, path: P, mode: LoadMode) -> Result<(), WorkspaceError> {
+        let source = fs::read_to_string(path)?;
+        self.load_source(&source, mode)
+    }
+}
@@ -0,0 +1,55 @@
+use std::fs;
+use std::path::PathBuf;
+
+use rustyline::{Editor, Helper};
+
+use crate::config;
+
+/// Where input history is kept between sessions, and how much of it
+pub struct HistorySettings {
+    /// The history file, history is not persisted if this is `None`
+    pub path: Option<PathBuf>,
+    /// The maximum number of entries kept
+    pub max_size: usize,
+}
+
+impl Default for HistorySettings {
+    fn default() -> HistorySettings {
+        HistorySettings {
+            path: data_dir().map(|dir| dir.join("history")),
+            max_size: 1000,
+        }
+    }
+}
+
+impl HistorySettings {
+    /// Loads the history file into the editor, a missing file is not an error
+    pub fn load<H: Helper>(&self, editor: &mut Editor<H>) {
+        if let Some(path) = &self.path {
+            if path.exists() {
+                if let Err(e) = editor.load_history(path) {
+                    eprintln!("Could not load history from {}: {}", path.display(), e);
+                }
+            }
+        }
+    }
+
+    pub fn save<H: Helper>(&self, editor: &mut Editor<H>) {
+        if let Some(path) = &self.path {
+            if let Some(dir) = path.parent() {
+                if let Err(e) = fs::create_dir_all(dir) {
+                    eprintln!("Could not create {}: {}", dir.display(), e);
+                    return;
+                }
+            }
+            if let Err(e) = editor.save_history(path) {
+                eprintln!("Could not save history to {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// The directory for slide's data
+pub fn data_dir() -> Option<PathBuf> {
+    config::xdg_dir("XDG_DATA_HOME", ".local/share")
+}
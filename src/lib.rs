@@ -1,6 +1,9 @@
 pub use parsing::{ast, token};
 
 // pub mod ast;
+pub mod config;
+pub mod diagnostic;
 pub mod error;
 pub mod eval;
+pub mod history;
 // pub mod token;
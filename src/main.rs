@@ -1,151 +1,405 @@
+use std::cell::RefCell;
+use std::io::{self, BufRead, IsTerminal};
+use std::rc::Rc;
+
 use rustyline::{error::ReadlineError, Config, Editor};
 
+mod commands;
 mod prompt_helper;
 
 use slide::*;
 
+use slide::config::{Prompts, SlideConfig};
+use slide::diagnostic::Diagnostic;
+
 struct SlideContext {
-    editor_ctx: Editor<prompt_helper::MathHelper>,
-    eval_ctx: eval::EvalContext,
+    /// Shared with the prompt helper, which reads it for completions
+    eval_ctx: Rc<RefCell<eval::EvalContext>>,
+    /// Print the result of every statement on a line, rather than only the last
+    print_all_results: bool,
+    /// Whether input is typed at a prompt, otherwise results are printed bare
+    /// and errors go to stderr
+    interactive: bool,
+    /// Set by `:quit`, stops reading any more input
+    quit: bool,
+    /// Whether results are left unprinted, errors are still shown
+    quiet: bool,
+    prompts: Prompts,
 }
 
 impl SlideContext {
-    fn new() -> SlideContext {
-        let helper = prompt_helper::MathHelper;
-        let config = Config::builder()
-            .history_ignore_space(true)
-            .auto_add_history(true)
-            .build();
-        let mut editor = Editor::with_config(config);
-        editor.set_helper(Some(helper));
+    fn new(print_all_results: bool, interactive: bool, prompts: Prompts) -> SlideContext {
         SlideContext {
-            editor_ctx: editor,
-            eval_ctx: eval::EvalContext::new(),
+            eval_ctx: Rc::new(RefCell::new(eval::EvalContext::new())),
+            print_all_results,
+            interactive,
+            quit: false,
+            quiet: false,
+            prompts,
         }
     }
 
-    pub fn eval_line(&mut self, input: &str) {
-        if input.is_empty() {
-            return;
+    /// Evaluates a line of input, printing the results
+    ///
+    /// `first_line` is the line number of the input within the script it is
+    /// from, used in errors. Returns false if there were any errors
+    pub fn eval_line(&mut self, input: &str, first_line: usize) -> bool {
+        if input.trim().is_empty() {
+            return true;
         }
-        let tokens = match token::tokenize(input) {
-            Ok(tokens) => tokens,
-            Err(e) => {
-                self.print_lex_error(e);
-                return;
-            }
+        if input.trim_start().starts_with(':') {
+            return self.run_command(input.trim(), first_line);
+        }
+        let nodes = match self.parse(input, first_line) {
+            Some(nodes) => nodes,
+            None => return false,
         };
 
+        let count = nodes.len();
+        for (i, node) in nodes.into_iter().enumerate() {
+            let is_last = i + 1 == count;
+            let result = {
+                let mut eval_ctx = self.eval_ctx.borrow_mut();
+                let style = eval_ctx.rational_style;
+                let prec = eval_ctx.precision();
+                let result = eval_ctx.eval(node).map(|result| result.format(style, prec));
+                result.map(|result| (eval_ctx.results().len(), result))
+            };
+            match result {
+                Ok((number, result)) => {
+                    if self.print_all_results || is_last {
+                        self.print_numbered_result(number, &result);
+                    }
+                }
+                Err(e) => {
+                    self.print_diagnostic(&error::eval_diagnostic(&e), input, first_line);
+                    return false;
+                }
+            }
+        }
+        true
+    }
+
+    /// Tokenizes the input, printing any errors
+    fn tokenize<'a>(
+        &self,
+        input: &'a str,
+        first_line: usize,
+    ) -> Option<Vec<token::SpannedToken<'a>>> {
+        let prec = self.eval_ctx.borrow().precision();
+        match token::tokenize_with_precision(input, prec) {
+            Ok(tokens) => Some(tokens),
+            Err(errors) => {
+                for e in &errors {
+                    self.print_diagnostic(&error::lex_diagnostic(e), input, first_line);
+                }
+                None
+            }
+        }
+    }
+
+    /// Tokenizes and parses the input, printing any errors
+    fn parse(&self, input: &str, first_line: usize) -> Option<Vec<ast::SpannedNode>> {
+        let tokens = self.tokenize(input, first_line)?;
+
         // Translate the tokens into a form lalrpop likes
         let mut lalr_tokens = Vec::new();
         for token in tokens {
-            lalr_tokens.push(Ok((token.1.start as usize, token.0, token.1.end as usize)))
+            lalr_tokens.push(Ok((token.1.start, token.0, token.1.end)))
         }
 
         let mut errors = Vec::new();
         let nodes = match ast::parse(&mut errors, lalr_tokens) {
             Err(err) => {
-                self.print_parse_error(err, &input);
-                return;
+                self.print_diagnostic(&error::parse_diagnostic(&err, input), input, first_line);
+                return None;
             }
             Ok(n) => n,
         };
 
-        if errors.is_empty() {
-            println!("=> {}", self.eval(nodes[0].clone()));
+        if !errors.is_empty() {
+            for err in &errors {
+                self.print_diagnostic(
+                    &error::parse_diagnostic(&err.error, input),
+                    input,
+                    first_line,
+                );
+            }
+            return None;
+        }
+        Some(nodes)
+    }
+
+    fn print_result(&self, result: &str) {
+        if self.quiet {
+            return;
+        }
+        if self.interactive {
+            println!("{}{}", self.prompts.result, result);
         } else {
-            self.print_errors(&errors, &input);
+            println!("{}", result);
         }
     }
 
-    fn eval(&mut self, node: ast::Node) -> &ast::Number {
-        self.eval_ctx.eval(node)
+    /// Prints a result along with the number used to recall it, like `=> [2] 5`
+    fn print_numbered_result(&self, number: usize, result: &str) {
+        if self.quiet {
+            return;
+        }
+        if self.interactive {
+            println!("{}[{}] {}", self.prompts.result, number, result);
+        } else {
+            println!("{}", result);
+        }
     }
 
-    fn print_errors(&self, errs: &[ast::TErrorRecovery], input: &str) {
-        let mut dump = true;
-        for err in errs {
-            let (start, end) = match error_to_range(err) {
-                (0, 0) => (input.len(), input.len()),
-                l => l,
-            };
+    fn print_error(&self, message: &str) {
+        self.print_diagnostic(&Diagnostic::new(message), "", 1);
+    }
 
-            if end.saturating_sub(start) <= 1 {
-                println!("   {}^", " ".repeat(start))
+    /// Prints a diagnostic, showing the parts of the input it refers to
+    ///
+    /// `first_line` is the line number shown for the first line of the input
+    fn print_diagnostic(&self, diagnostic: &Diagnostic, input: &str, first_line: usize) {
+        for line in diagnostic.render(input, first_line) {
+            if self.interactive {
+                println!("{}{}", self.prompts.error, line);
             } else {
-                println!(
-                    "   {}{}",
-                    " ".repeat(start),
-                    "~".repeat(end.saturating_sub(start))
-                )
-            }
-            if let Some(human_err) = error::try_humanize(err) {
-                println!("=# {}", human_err);
-                dump = false;
-            }
-        }
-        if dump {
-            for err in errs {
-                println!("=# =====");
-                for l in format!("{:#?}", err).lines() {
-                    println!("=# {}", l);
+                eprintln!("{}", line);
+            }
+        }
+    }
+
+    /// Evaluates each line from a reader, stopping at the first error
+    ///
+    /// Lines with unclosed brackets or strings are joined with the following lines.
+    /// Returns false if there were any errors
+    fn eval_lines<R: BufRead>(&mut self, reader: R) -> bool {
+        let mut input = String::new();
+        let mut start_line = 1;
+        for (i, line) in reader.lines().enumerate() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    self.print_error(&e.to_string());
+                    return false;
                 }
+            };
+            // Allow scripts to be run directly with a shebang
+            if i == 0 && line.starts_with("#!") {
+                continue;
+            }
+            if input.is_empty() {
+                start_line = i + 1;
+            } else {
+                input.push('\n');
+            }
+            input.push_str(&line);
+            if prompt_helper::is_incomplete(&input) {
+                continue;
+            }
+            if !self.eval_line(&input, start_line) {
+                return false;
             }
+            if self.quit {
+                return true;
+            }
+            input.clear();
         }
+        self.eval_line(&input, start_line)
     }
 
-    fn print_parse_error(&self, error: ast::TParseError, input: &str) {
-        self.print_errors(
-            &[lalrpop_util::ErrorRecovery {
-                error,
-                dropped_tokens: vec![],
-            }],
-            input,
-        )
+    /// Evaluates the init file into the context, if there is one
+    ///
+    /// The file is run like a script without printing results, stopping at
+    /// the first error. Its results are not kept, so results typed at the
+    /// prompt are numbered from 1
+    fn run_init(&self) {
+        let path = match config::init_path() {
+            Some(path) => path,
+            None => return,
+        };
+        if !path.exists() {
+            return;
+        }
+        let mut init_ctx = SlideContext {
+            eval_ctx: Rc::clone(&self.eval_ctx),
+            quiet: true,
+            ..SlideContext::new(true, false, self.prompts.clone())
+        };
+        let ok = match std::fs::File::open(&path) {
+            Ok(file) => init_ctx.eval_lines(io::BufReader::new(file)),
+            Err(e) => {
+                init_ctx.print_error(&format!("Could not open {}: {}", path.display(), e));
+                false
+            }
+        };
+        if !ok {
+            eprintln!("Stopped running {} after an error", path.display());
+        }
+        self.eval_ctx.borrow_mut().clear_results();
     }
 
-    fn print_lex_error(&self, err: parsing::token::SpannedError<'_>) {
-        match err.0 {
-            parsing::token::Error::PestErr(pest_err) => {
-                let (start, _) = parsing::token::span_from_loc(pest_err.location);
-                println!("   {}~", " ".repeat(start));
-                println!("=# Invalid token");
+    fn run_repl(&mut self, config: &SlideConfig) {
+        let history = &config.history;
+        let editor_config = Config::builder()
+            .history_ignore_space(true)
+            .history_ignore_dups(true)
+            .max_history_size(history.max_size)
+            .auto_add_history(true)
+            .edit_mode(config.edit_mode)
+            .build();
+        let mut editor = Editor::with_config(editor_config);
+        let theme = if config.colors {
+            Some(config.theme.clone())
+        } else {
+            None
+        };
+        editor.set_helper(Some(prompt_helper::MathHelper::new(
+            self.eval_ctx.clone(),
+            theme,
+        )));
+        history.load(&mut editor);
+
+        let mut input = String::new();
+        loop {
+            let prompt = if input.is_empty() {
+                &self.prompts.input
+            } else {
+                &self.prompts.continuation
+            };
+            let line = match editor.readline(prompt) {
+                Ok(line) => line,
+                // Abandon a partially entered expression
+                Err(ReadlineError::Interrupted) if !input.is_empty() => {
+                    input.clear();
+                    continue;
+                }
+                Err(ReadlineError::Interrupted) | Err(ReadlineError::Eof) => {
+                    break;
+                }
+                Err(e) => {
+                    self.print_error(&e.to_string());
+                    continue;
+                }
+            };
+            if !input.is_empty() {
+                input.push('\n');
+            }
+            input.push_str(&line);
+            if prompt_helper::is_incomplete(&input) {
+                continue;
+            }
+            self.eval_line(&input, 1);
+            if self.quit {
+                break;
             }
-            _ => println!("{:#?}", err),
+            input.clear();
         }
+        history.save(&mut editor);
     }
 }
 
-fn main() {
-    let mut slide_ctx = SlideContext::new();
+/// What to evaluate, as chosen on the command line
+enum Input {
+    Expressions(Vec<String>),
+    Script(String),
+    Stdin,
+}
 
-    loop {
-        let input = match slide_ctx.editor_ctx.readline("<< ") {
-            Ok(line) => line,
-            Err(ReadlineError::Interrupted) => {
-                break;
+fn main() {
+    let interactive = io::stdin().is_terminal();
+    let (mut config, warnings) = SlideConfig::load();
+    for warning in warnings {
+        eprintln!("warning: {}", warning);
+    }
+    let mut slide_ctx = SlideContext::new(true, interactive, config.prompts.clone());
+    if let Some(prec) = config.precision {
+        // Already checked when reading the config
+        let _ = slide_ctx.eval_ctx.borrow_mut().set_precision(prec);
+    }
+    let mut expressions = Vec::new();
+    let mut script = None;
+    let mut run_init = true;
+    let history = &mut config.history;
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--last-only" => slide_ctx.print_all_results = false,
+            "--decimal" => {
+                slide_ctx.eval_ctx.borrow_mut().rational_style = eval::RationalStyle::Decimal
             }
-            Err(e) => {
-                for l in format!("{:#?}", e).lines() {
-                    println!("=# {}", l);
+            "-p" | "--precision" => {
+                let prec = args.next().and_then(|prec| prec.parse().ok());
+                match prec.map(|prec| slide_ctx.eval_ctx.borrow_mut().set_precision(prec)) {
+                    Some(Ok(())) => {}
+                    Some(Err(e)) => usage_error(&e.to_string()),
+                    None => usage_error(&format!("{} expects a number of bits", arg)),
                 }
-                continue;
             }
-        };
-        slide_ctx.eval_line(&input)
+            "--no-init" => run_init = false,
+            "--no-history" => history.path = None,
+            "--history-file" => match args.next() {
+                Some(path) => history.path = Some(path.into()),
+                None => usage_error(&format!("{} expects a path", arg)),
+            },
+            "--history-size" => match args.next().and_then(|size| size.parse().ok()) {
+                Some(size) => history.max_size = size,
+                None => usage_error(&format!("{} expects a number of entries", arg)),
+            },
+            "-e" | "--eval" => match args.next() {
+                Some(expr) => expressions.push(expr),
+                None => usage_error(&format!("{} expects an expression", arg)),
+            },
+            _ if arg.starts_with('-') && arg != "-" => {
+                usage_error(&format!("Unknown argument `{}`", arg))
+            }
+            _ if script.is_none() => script = Some(arg),
+            _ => usage_error(&format!("Unexpected argument `{}`", arg)),
+        }
+    }
+
+    if run_init {
+        slide_ctx.run_init();
     }
-}
 
-fn error_to_range(err: &ast::TErrorRecovery) -> (usize, usize) {
-    use lalrpop_util::ParseError;
-    match err.error {
-        ParseError::ExtraToken {
-            token: (start, _, end),
-        } => (start, end),
-        ParseError::InvalidToken { location } => (location, location),
-        ParseError::UnrecognizedToken { ref token, .. } => {
-            token.clone().map(|t| (t.0, t.2)).unwrap_or((0, 0))
+    let input = match (script, expressions.is_empty()) {
+        (Some(_), false) => usage_error("Cannot use both -e and a script file"),
+        (Some(ref path), true) if path == "-" => Input::Stdin,
+        (Some(path), true) => Input::Script(path),
+        (None, false) => Input::Expressions(expressions),
+        (None, true) if interactive => return slide_ctx.run_repl(&config),
+        (None, true) => Input::Stdin,
+    };
+
+    slide_ctx.interactive = false;
+    let ok = match input {
+        Input::Expressions(expressions) => expressions
+            .iter()
+            .all(|e| slide_ctx.quit || slide_ctx.eval_line(e, 1)),
+        Input::Script(path) => match std::fs::File::open(&path) {
+            Ok(file) => slide_ctx.eval_lines(io::BufReader::new(file)),
+            Err(e) => {
+                slide_ctx.print_error(&format!("Could not open `{}`: {}", path, e));
+                false
+            }
+        },
+        Input::Stdin => {
+            let stdin = io::stdin();
+            let lock = stdin.lock();
+            slide_ctx.eval_lines(lock)
         }
-        ParseError::User { .. } => (0, 0),
+    };
+    if !ok {
+        std::process::exit(1);
     }
 }
+
+fn usage_error(message: &str) -> ! {
+    eprintln!("error: {}", message);
+    eprintln!(
+        "usage: slide [--last-only] [--decimal] [-p BITS] [--no-init] [--no-history] \
+         [--history-file PATH] [--history-size N] [-e EXPR]... [FILE]"
+    );
+    std::process::exit(2);
+}
@@ -1,10 +1,99 @@
 use std::borrow::Cow;
+use std::cell::RefCell;
+use std::rc::Rc;
 
 use rustyline::{
     completion::Completer, error::ReadlineError, highlight::Highlighter, hint::Hinter, Helper,
 };
 
-pub struct MathHelper;
+use slide::ast;
+use slide::config::Theme;
+use slide::eval::EvalContext;
+use slide::token::{self, SpannedError, SpannedToken, Token};
+
+const KEYWORDS: &[&str] = &["let", "fn"];
+
+pub struct MathHelper {
+    eval_ctx: Rc<RefCell<EvalContext>>,
+    /// Colours for highlighting, `None` disables highlighting
+    theme: Option<Theme>,
+}
+
+impl MathHelper {
+    pub fn new(eval_ctx: Rc<RefCell<EvalContext>>, theme: Option<Theme>) -> MathHelper {
+        MathHelper { eval_ctx, theme }
+    }
+
+    /// Colours each token in the line, returns `None` if the line does not lex
+    fn highlight_tokens(&self, line: &str, theme: &Theme) -> Option<String> {
+        let tokens = token::tokenize(line).ok()?;
+        let eval_ctx = self.eval_ctx.borrow();
+        let mut out = String::with_capacity(line.len());
+        let mut last_end = 0;
+        let mut defining = false;
+        for SpannedToken(token, span) in tokens {
+            let (start, end) = (span.start, span.end);
+            out.push_str(&line[last_end..start]);
+            // Names being defined by `let` and `fn` count as known
+            let known = match token {
+                Token::Ident(name) => defining || eval_ctx.is_defined(name),
+                _ => false,
+            };
+            defining = matches!(token, Token::Let | Token::Fun);
+            match theme.token_color(&token, known) {
+                Some(color) => {
+                    out.push_str(&format!("\x1b[{}m{}\x1b[0m", color, &line[start..end]))
+                }
+                None => out.push_str(&line[start..end]),
+            }
+            last_end = end;
+        }
+        out.push_str(&line[last_end..]);
+        Some(out)
+    }
+}
+
+/// Whether the input has unclosed brackets or string literals, meaning more
+/// lines should be read before evaluating it
+///
+/// rustyline 2.1 has no `Validator` to hold the line open, so the read loop
+/// calls this after each line and keeps reading with a continuation prompt
+pub fn is_incomplete(input: &str) -> bool {
+    if has_open_string(input) {
+        return true;
+    }
+    let tokens = match token::tokenize(input) {
+        Ok(tokens) => tokens,
+        Err(_) => return false,
+    };
+    let depth = tokens
+        .iter()
+        .fold(0isize, |depth, SpannedToken(token, _)| match token {
+            Token::LParen | Token::LBracket | Token::LBrace | Token::LAngleBracket => depth + 1,
+            Token::RParen | Token::RBracket | Token::RBrace | Token::RAngleBracket => depth - 1,
+            _ => depth,
+        });
+    depth > 0
+}
+
+fn has_open_string(input: &str) -> bool {
+    let mut in_string = false;
+    let mut chars = input.chars();
+    while let Some(ch) = chars.next() {
+        match ch {
+            '"' => in_string = !in_string,
+            '\\' if in_string => {
+                chars.next();
+            }
+            _ => {}
+        }
+    }
+    in_string
+}
+
+fn is_ident_char(ch: char) -> bool {
+    ch.is_ascii_alphanumeric() || ch == '_'
+}
 
 impl Completer for MathHelper {
     type Candidate = String;
@@ -14,30 +103,109 @@ impl Completer for MathHelper {
         line: &str,
         pos: usize,
     ) -> Result<(usize, Vec<Self::Candidate>), ReadlineError> {
-        ().complete(line, pos)
+        let start = line[..pos]
+            .char_indices()
+            .rev()
+            .take_while(|&(_, ch)| is_ident_char(ch))
+            .last()
+            .map_or(pos, |(i, _)| i);
+        let word = &line[start..pos];
+
+        // Meta-commands are only valid at the very start of a line
+        if line[..start].trim_start() == ":" {
+            let start = line.find(':').unwrap(); // Safe, the prefix is a `:`
+            let mut candidates: Vec<String> = crate::commands::COMMANDS
+                .iter()
+                .filter(|(cmd, _, _)| cmd[1..].starts_with(word))
+                .map(|(cmd, _, _)| cmd.to_string())
+                .collect();
+            candidates.sort();
+            return Ok((start, candidates));
+        }
+        if word.is_empty() || word.starts_with(|ch: char| ch.is_ascii_digit()) {
+            return Ok((pos, vec![]));
+        }
+
+        let eval_ctx = self.eval_ctx.borrow();
+        let mut candidates: Vec<String> = eval_ctx
+            .values()
+            .map(|(name, _)| name)
+            .chain(eval_ctx.functions().map(|(name, _)| name))
+            .chain(eval_ctx.constants().iter().cloned())
+            .chain(KEYWORDS.iter().cloned())
+            .filter(|name| name.starts_with(word))
+            .map(|name| name.to_owned())
+            .collect();
+        candidates.sort();
+        candidates.dedup();
+        Ok((start, candidates))
     }
 }
 
 impl Hinter for MathHelper {
-    fn hint(&self, _line: &str, _pos: usize) -> Option<String> {
-        None
-    }
-}
+    /// Previews the result of the line, evaluated in a snapshot of the context
+    ///
+    /// The snapshot gives up on lines that recurse too deeply or would build
+    /// huge numbers, which are then not previewed
+    fn hint(&self, line: &str, pos: usize) -> Option<String> {
+        if pos < line.len() {
+            return None;
+        }
+        let mut eval_ctx = self.eval_ctx.borrow().snapshot();
+        let tokens = token::tokenize_with_precision(line, eval_ctx.precision()).ok()?;
+        let lalr_tokens = tokens
+            .into_iter()
+            .map(|token| Ok((token.1.start, token.0, token.1.end)))
+            .collect();
+        let mut errors = Vec::new();
+        let nodes = ast::parse(&mut errors, lalr_tokens).ok()?;
+        if !errors.is_empty() {
+            return None;
+        }
 
-/// Basic Pattern to match operators
-fn op_formatter(ch: char) -> String {
-    match ch {
-        '*' | '/' | '+' | '-' | '%' | '!' => format!("\x1b[1m{}\x1b[0m", ch),
-        '#' => format!("\x1b[31m{}\x1b[0m", ch),
-        _ => ch.to_string(),
+        let mut result = None;
+        for node in nodes {
+            result = Some(eval_ctx.eval(node).ok()?.clone());
+        }
+        let result = result?.format(eval_ctx.rational_style, eval_ctx.precision());
+        // Don't repeat a line that is already just its value, such as a number
+        if result == line.trim() {
+            return None;
+        }
+        Some(format!(" = {}", result))
     }
 }
 
 impl Highlighter for MathHelper {
-    // TODO: Use tokens/ast to highlight this better
+    fn highlight_hint<'h>(&self, hint: &'h str) -> Cow<'h, str> {
+        match &self.theme {
+            Some(theme) => Cow::Owned(format!("\x1b[{}m{}\x1b[0m", theme.hint, hint)),
+            None => Cow::Borrowed(hint),
+        }
+    }
+
     fn highlight<'l>(&self, line: &'l str, _pos: usize) -> Cow<'l, str> {
-        let out = line.chars().map(op_formatter).collect();
-        Cow::Owned(out)
+        let theme = match &self.theme {
+            Some(theme) => theme,
+            None => return Cow::Borrowed(line),
+        };
+        if let Some(out) = self.highlight_tokens(line, theme) {
+            return Cow::Owned(out);
+        }
+        // Highlight as much as possible of a line that does not lex yet, such
+        // as one with an unterminated string
+        let valid_up_to = match token::tokenize(line) {
+            Err(errors) => errors.first().map_or(0, |SpannedError(_, span)| span.start),
+            Ok(_) => 0,
+        };
+        if !line.is_char_boundary(valid_up_to) {
+            return Cow::Borrowed(line);
+        }
+        let (valid, rest) = line.split_at(valid_up_to);
+        match self.highlight_tokens(valid, theme) {
+            Some(out) => Cow::Owned(out + rest),
+            None => Cow::Borrowed(line),
+        }
     }
 }
 
@@ -1,11 +1,14 @@
-use rug::{Float as RFloat, Integer};
+use rug::{Complex, Float as RFloat, Integer, Rational};
 use slide::{
     ast::{
         self,
         Node::{self, *},
-        Number,
+        Number, Recall, Spanned, SpannedNode,
     },
-    eval::EvalContext,
+    config::{SlideConfig, Theme},
+    diagnostic::Diagnostic,
+    error,
+    eval::{EvalContext, EvalError, Function, LoadMode, RationalStyle, Value, WorkspaceError},
     token,
 };
 
@@ -17,6 +20,14 @@ fn float(prec: u32, v: f64) -> Number {
     Number::Float(RFloat::with_val(prec, v))
 }
 
+fn int_value(v: isize) -> Value {
+    Value::Number(int(v))
+}
+
+fn float_value(prec: u32, v: f64) -> Value {
+    Value::Number(float(prec, v))
+}
+
 fn wrapped_int(v: isize) -> Node {
     Node::Number(int(v))
 }
@@ -25,31 +36,46 @@ fn wrapped_float(prec: u32, v: f64) -> Node {
     Node::Number(float(prec, v))
 }
 
-fn boxed_int(v: isize) -> Box<Node> {
-    Box::new(wrapped_int(v))
+/// A node with a placeholder span, spans are ignored when comparing nodes
+fn spanned(node: Node) -> SpannedNode {
+    Spanned::new(node, 0, 0)
+}
+
+fn boxed_int(v: isize) -> Box<SpannedNode> {
+    Box::new(spanned(wrapped_int(v)))
 }
 
-fn boxed_float(prec: u32, v: f64) -> Box<Node> {
-    Box::new(wrapped_float(prec, v))
+fn boxed_float(prec: u32, v: f64) -> Box<SpannedNode> {
+    Box::new(spanned(wrapped_float(prec, v)))
 }
 
-fn parse_str(input: &str) -> Node {
+fn parse_str(input: &str) -> SpannedNode {
     let mut errors = Vec::new();
     let tokens = token::tokenize(input).unwrap();
     let mut lalr_tokens = Vec::new();
     for token in tokens {
-        lalr_tokens.push(Ok((token.1.start as usize, token.0, token.1.end as usize)))
+        lalr_tokens.push(Ok((token.1.start, token.0, token.1.end)))
     }
     ast::parse_single(&mut errors, lalr_tokens).unwrap()
 }
 
+fn parse_all(input: &str) -> Vec<SpannedNode> {
+    let mut errors = Vec::new();
+    let tokens = token::tokenize(input).unwrap();
+    let mut lalr_tokens = Vec::new();
+    for token in tokens {
+        lalr_tokens.push(Ok((token.1.start, token.0, token.1.end)))
+    }
+    ast::parse(&mut errors, lalr_tokens).unwrap()
+}
+
 #[test]
 fn add() {
     let result = parse_str("1+ 1");
     let mut eval_ctx = EvalContext::new();
-    assert_eq!(*eval_ctx.eval(result.clone()), int(2));
+    assert_eq!(*eval_ctx.eval(result.clone()).unwrap(), int_value(2));
     assert_eq!(
-        result,
+        result.node,
         Infix {
             lhs: boxed_int(1),
             op: "+".to_string(),
@@ -58,9 +84,12 @@ fn add() {
     );
 
     let result = parse_str("1.25 + 1.75");
-    assert_eq!(*eval_ctx.eval(result.clone()), float(53, 3.0));
     assert_eq!(
-        result,
+        *eval_ctx.eval(result.clone()).unwrap(),
+        float_value(53, 3.0)
+    );
+    assert_eq!(
+        result.node,
         Infix {
             lhs: boxed_float(53, 1.25),
             op: "+".to_string(),
@@ -73,22 +102,871 @@ fn add() {
 fn hex() {
     let result = parse_str("0xFF");
     let mut eval_ctx = EvalContext::new();
-    assert_eq!(result, wrapped_int(255));
-    assert_eq!(*eval_ctx.eval(result), int(255));
+    assert_eq!(result.node, wrapped_int(255));
+    assert_eq!(*eval_ctx.eval(result).unwrap(), int_value(255));
 
     let result = parse_str("27h");
-    assert_eq!(result, wrapped_int(39));
-    assert_eq!(*eval_ctx.eval(result), int(39));
+    assert_eq!(result.node, wrapped_int(39));
+    assert_eq!(*eval_ctx.eval(result).unwrap(), int_value(39));
 }
 
 #[test]
 fn binary() {
     let result = parse_str("0b1010101");
     let mut eval_ctx = EvalContext::new();
-    assert_eq!(result, wrapped_int(85));
-    assert_eq!(*eval_ctx.eval(result), int(85));
+    assert_eq!(result.node, wrapped_int(85));
+    assert_eq!(*eval_ctx.eval(result).unwrap(), int_value(85));
 
     let result = parse_str("0b00000011");
-    assert_eq!(result, wrapped_int(3));
-    assert_eq!(*eval_ctx.eval(result), int(3));
+    assert_eq!(result.node, wrapped_int(3));
+    assert_eq!(*eval_ctx.eval(result).unwrap(), int_value(3));
+}
+
+#[test]
+fn precedence() {
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(*eval_ctx.eval(parse_str("1+2*3")).unwrap(), int_value(7));
+    assert_eq!(*eval_ctx.eval(parse_str("2*3+1")).unwrap(), int_value(7));
+    assert_eq!(*eval_ctx.eval(parse_str("(1+2)*3")).unwrap(), int_value(9));
+    assert_eq!(*eval_ctx.eval(parse_str("10-4-3")).unwrap(), int_value(3));
+
+    let result = parse_str("1+2*3");
+    assert_eq!(
+        result.node,
+        Infix {
+            lhs: boxed_int(1),
+            op: "+".to_string(),
+            rhs: Box::new(spanned(Infix {
+                lhs: boxed_int(2),
+                op: "*".to_string(),
+                rhs: boxed_int(3)
+            }))
+        }
+    );
+}
+
+#[test]
+fn shifts() {
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(*eval_ctx.eval(parse_str("1 << 4")).unwrap(), int_value(16));
+    assert_eq!(*eval_ctx.eval(parse_str("256>>2")).unwrap(), int_value(64));
+    assert_eq!(
+        *eval_ctx.eval(parse_str("1 << 2 + 1")).unwrap(),
+        int_value(8)
+    );
+    assert_eq!(
+        parse_str("1+2<<3").node,
+        Infix {
+            lhs: Box::new(spanned(Infix {
+                lhs: boxed_int(1),
+                op: "+".to_string(),
+                rhs: boxed_int(2)
+            })),
+            op: "<<".to_string(),
+            rhs: boxed_int(3)
+        }
+    );
+    assert_eq!(
+        parse_str("64>>1>>2").node,
+        Infix {
+            lhs: Box::new(spanned(Infix {
+                lhs: boxed_int(64),
+                op: ">>".to_string(),
+                rhs: boxed_int(1)
+            })),
+            op: ">>".to_string(),
+            rhs: boxed_int(2)
+        }
+    );
+
+    // `>>` closing nested calls is not a shift
+    eval_ctx.eval(parse_str("fn id<x>{x}")).unwrap();
+    assert_eq!(
+        *eval_ctx.eval(parse_str("id<id<3>>")).unwrap(),
+        int_value(3)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("id<(12 >> 2)>")).unwrap(),
+        int_value(3)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("id<1 << 3>")).unwrap(),
+        int_value(8)
+    );
+}
+
+#[test]
+fn right_associative_pow() {
+    let result = parse_str("2**3**2");
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(
+        result.node,
+        Infix {
+            lhs: boxed_int(2),
+            op: "**".to_string(),
+            rhs: Box::new(spanned(Infix {
+                lhs: boxed_int(3),
+                op: "**".to_string(),
+                rhs: boxed_int(2)
+            }))
+        }
+    );
+    assert_eq!(*eval_ctx.eval(result).unwrap(), int_value(512));
+    assert_eq!(*eval_ctx.eval(parse_str("2*3**2")).unwrap(), int_value(18));
+}
+
+#[test]
+fn unary() {
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(
+        parse_str("-1").node,
+        Prefix {
+            op: "-".to_string(),
+            rhs: boxed_int(1)
+        }
+    );
+    assert_eq!(
+        parse_str("2-1").node,
+        Infix {
+            lhs: boxed_int(2),
+            op: "-".to_string(),
+            rhs: boxed_int(1)
+        }
+    );
+    assert_eq!(*eval_ctx.eval(parse_str("(2)-1")).unwrap(), int_value(1));
+    assert_eq!(*eval_ctx.eval(parse_str("-(1+2)")).unwrap(), int_value(-3));
+    assert_eq!(*eval_ctx.eval(parse_str("3--2")).unwrap(), int_value(5));
+    assert_eq!(*eval_ctx.eval(parse_str("-2**2")).unwrap(), int_value(-4));
+    assert_eq!(*eval_ctx.eval(parse_str("2*-3")).unwrap(), int_value(-6));
+    assert_eq!(*eval_ctx.eval(parse_str("~5")).unwrap(), int_value(-6));
+    assert_eq!(*eval_ctx.eval(parse_str("!0")).unwrap(), int_value(1));
+    assert_eq!(*eval_ctx.eval(parse_str("!7")).unwrap(), int_value(0));
+    assert_eq!(*eval_ctx.eval(parse_str("!0 + 1")).unwrap(), int_value(2));
+
+    eval_ctx.eval(parse_str("let x=3")).unwrap();
+    assert_eq!(*eval_ctx.eval(parse_str("x-1")).unwrap(), int_value(2));
+    assert_eq!(*eval_ctx.eval(parse_str("-x")).unwrap(), int_value(-3));
+}
+
+#[test]
+fn multiple_statements() {
+    let nodes = parse_all("let a=2; let b=3; a*b");
+    assert_eq!(nodes.len(), 3);
+    let mut eval_ctx = EvalContext::new();
+    let results: Vec<_> = nodes
+        .into_iter()
+        .map(|node| eval_ctx.eval(node).unwrap().clone())
+        .collect();
+    assert_eq!(results, vec![int_value(2), int_value(3), int_value(6)]);
+
+    let nodes = parse_all("2; #*5");
+    let mut eval_ctx = EvalContext::new();
+    let mut last = None;
+    for node in nodes {
+        last = Some(eval_ctx.eval(node).unwrap().clone());
+    }
+    assert_eq!(last, Some(int_value(10)));
+}
+
+#[test]
+fn whitespace() {
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(
+        *eval_ctx.eval(parse_str("1\t+\r\n2")).unwrap(),
+        int_value(3)
+    );
+    let nodes = parse_all("fn add<a,\tb>{\r\n  a + b\r\n};\nadd<1, 2>");
+    assert_eq!(nodes.len(), 2);
+    let mut last = None;
+    for node in nodes {
+        last = Some(eval_ctx.eval(node).unwrap().clone());
+    }
+    assert_eq!(last, Some(int_value(3)));
+}
+
+#[test]
+fn recall() {
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(*eval_ctx.eval(parse_str("#")).unwrap(), int_value(0));
+    for node in parse_all("1; 2; 3") {
+        eval_ctx.eval(node).unwrap();
+    }
+    assert_eq!(eval_ctx.results().len(), 4);
+    assert_eq!(*eval_ctx.eval(parse_str("# + #")).unwrap(), int_value(6));
+    assert_eq!(*eval_ctx.eval(parse_str("#2 * 10")).unwrap(), int_value(10));
+    assert_eq!(*eval_ctx.eval(parse_str("##")).unwrap(), int_value(6));
+    assert_eq!(*eval_ctx.eval(parse_str("#-3")).unwrap(), int_value(6));
+    assert_eq!(*eval_ctx.eval(parse_str("# - 2")).unwrap(), int_value(4));
+
+    // `#-1` is a recall of the previous result, `# - 1` subtracts from it
+    assert_eq!(parse_str("#-1").node, Prev(Recall::Relative(1)));
+    assert_eq!(
+        parse_str("# - 1").node,
+        Infix {
+            lhs: Box::new(spanned(Prev(Recall::Relative(1)))),
+            op: "-".to_string(),
+            rhs: boxed_int(1)
+        }
+    );
+    assert_eq!(
+        eval_ctx.eval(parse_str("#100")).map_err(|e| e.error),
+        Err(EvalError::NoResult(Recall::Absolute(100)))
+    );
+    assert_eq!(
+        eval_ctx.eval(parse_str("#0")).map_err(|e| e.error),
+        Err(EvalError::NoResult(Recall::Absolute(0)))
+    );
+
+    eval_ctx.clear_results();
+    assert!(eval_ctx.results().is_empty());
+    assert_eq!(*eval_ctx.eval(parse_str("#")).unwrap(), int_value(0));
+}
+
+#[test]
+fn eval_errors() {
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(
+        eval_ctx.eval(parse_str("1/0")).map_err(|e| e.error),
+        Err(EvalError::DivisionByZero)
+    );
+    assert_eq!(
+        eval_ctx.eval(parse_str("5%0")).map_err(|e| e.error),
+        Err(EvalError::DivisionByZero)
+    );
+    assert_eq!(
+        eval_ctx.eval(parse_str("foo")).map_err(|e| e.error),
+        Err(EvalError::UnknownIdentifier {
+            name: "foo".to_string(),
+            suggestion: None
+        })
+    );
+    assert_eq!(
+        eval_ctx.eval(parse_str("sin<>")).map_err(|e| e.error),
+        Err(EvalError::ArityMismatch {
+            name: "sin".to_string(),
+            expected: 1,
+            found: 0
+        })
+    );
+    assert_eq!(
+        eval_ctx.eval(parse_str("1!2")).map_err(|e| e.error),
+        Err(EvalError::UnsupportedOperator("!".to_string()))
+    );
+    assert_eq!(*eval_ctx.eval(parse_str("7%3")).unwrap(), int_value(1));
+}
+
+#[test]
+fn function_values() {
+    let mut eval_ctx = EvalContext::new();
+    eval_ctx.eval(parse_str("fn double<x>{x*2}")).unwrap();
+    eval_ctx.eval(parse_str("fn twice<f, x>{f<f<x>>}")).unwrap();
+    assert_eq!(
+        *eval_ctx.eval(parse_str("twice<double, 3>")).unwrap(),
+        int_value(12)
+    );
+
+    eval_ctx.eval(parse_str("let g = double")).unwrap();
+    assert_eq!(*eval_ctx.eval(parse_str("g<5>")).unwrap(), int_value(10));
+    assert_eq!(
+        *eval_ctx.eval(parse_str("let s = sin")).unwrap(),
+        Value::Function(Function::Builtin("sin".to_string()))
+    );
+
+    eval_ctx.eval(parse_str("let f = 70000")).unwrap();
+    assert_eq!(
+        eval_ctx.eval(parse_str("f<1>")).map_err(|e| e.error),
+        Err(EvalError::NotCallable("f".to_string()))
+    );
+    assert_eq!(
+        eval_ctx.eval(parse_str("double+1")).map_err(|e| e.error),
+        Err(EvalError::InvalidOperands {
+            op: "+".to_string(),
+            lhs: "function",
+            rhs: "number"
+        })
+    );
+}
+
+#[test]
+fn values() {
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(
+        *eval_ctx.eval(parse_str("!false")).unwrap(),
+        Value::Bool(true)
+    );
+    assert_eq!(
+        eval_ctx
+            .eval(parse_str("fn empty<>{}"))
+            .unwrap()
+            .to_string(),
+        "fn empty<>"
+    );
+    assert_eq!(*eval_ctx.eval(parse_str("empty<>")).unwrap(), Value::Unit);
+
+    let list = Value::List(vec![
+        int_value(1),
+        Value::String("a".to_string()),
+        Value::Unit,
+    ]);
+    assert_eq!(list.to_string(), "[1, \"a\", ()]");
+    assert_eq!(list.type_name(), "list");
+    assert!(list.is_truthy());
+    assert!(!Value::List(vec![]).is_truthy());
+}
+
+fn string_value(s: &str) -> Value {
+    Value::String(s.to_string())
+}
+
+#[test]
+fn strings() {
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(
+        *eval_ctx.eval(parse_str("\"abc\"")).unwrap(),
+        string_value("abc")
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("\"foo\" + \"bar\"")).unwrap(),
+        string_value("foobar")
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str(r#""a\"b\\c\n\u{41}""#)).unwrap(),
+        string_value("a\"b\\c\nA")
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("len<\"héllo\">")).unwrap(),
+        int_value(5)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("upper<\"abc\">")).unwrap(),
+        string_value("ABC")
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("lower<\"ABC\">")).unwrap(),
+        string_value("abc")
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("substr<\"slide\", 1, 3>")).unwrap(),
+        string_value("lid")
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("split<\"a,b\", \",\">")).unwrap(),
+        Value::List(vec![string_value("a"), string_value("b")])
+    );
+    assert_eq!(
+        *eval_ctx
+            .eval(parse_str("contains<\"slide\", \"id\">"))
+            .unwrap(),
+        Value::Bool(true)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("repeat<\"ab\", 3>")).unwrap(),
+        string_value("ababab")
+    );
+    assert_eq!(
+        eval_ctx
+            .eval(parse_str("repeat<\"ab\", 2**62>"))
+            .map_err(|e| e.error),
+        Err(EvalError::TooLarge("repeat".to_string()))
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("num<\"0x1F\">")).unwrap(),
+        int_value(31)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("num<\"-12\">")).unwrap(),
+        int_value(-12)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("str<3>")).unwrap(),
+        string_value("3")
+    );
+    assert_eq!(
+        eval_ctx
+            .eval(parse_str("num<\"abc\">"))
+            .map_err(|e| e.error),
+        Err(EvalError::InvalidNumber("abc".to_string()))
+    );
+}
+
+#[test]
+fn rationals() {
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(*eval_ctx.eval(parse_str("1/3*3")).unwrap(), int_value(1));
+    assert_eq!(*eval_ctx.eval(parse_str("6/3")).unwrap(), int_value(2));
+    assert_eq!(
+        *eval_ctx.eval(parse_str("1/3 + 1/6")).unwrap(),
+        Value::Number(Number::Rational(Rational::from((1, 2))))
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("2**-2")).unwrap(),
+        Value::Number(Number::Rational(Rational::from((1, 4))))
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("(2/3)**2")).unwrap(),
+        Value::Number(Number::Rational(Rational::from((4, 9))))
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("7/2 % 1")).unwrap(),
+        Value::Number(Number::Rational(Rational::from((1, 2))))
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("1/2 + 0.25")).unwrap(),
+        float_value(53, 0.75)
+    );
+    assert_eq!(
+        *eval_ctx
+            .eval(parse_str("100000000000000000001/100000000000000000001"))
+            .unwrap(),
+        int_value(1)
+    );
+
+    let third = eval_ctx.eval(parse_str("-1/3")).unwrap().clone();
+    assert_eq!(third.to_string(), "-1/3");
+    assert_eq!(
+        third.format(RationalStyle::Decimal, 53),
+        Value::Number(float(53, -1.0) / int(3)).to_string()
+    );
+    assert_eq!(
+        eval_ctx.eval(parse_str("0**-1")).map_err(|e| e.error),
+        Err(EvalError::DivisionByZero)
+    );
+}
+
+fn complex_value(re: f64, im: f64) -> Value {
+    Value::Number(Number::Complex(Complex::with_val(53, (re, im))))
+}
+
+#[test]
+fn complex() {
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(
+        *eval_ctx.eval(parse_str("2i")).unwrap(),
+        complex_value(0.0, 2.0)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("1+i")).unwrap(),
+        complex_value(1.0, 1.0)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("i*i")).unwrap(),
+        complex_value(-1.0, 0.0)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("sqrt<-4>")).unwrap(),
+        complex_value(0.0, 2.0)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("(3+4i)/(1/2)")).unwrap(),
+        complex_value(6.0, 8.0)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("re<3+4i>")).unwrap(),
+        float_value(53, 3.0)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("im<3+4i>")).unwrap(),
+        float_value(53, 4.0)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("conj<3+4i>")).unwrap(),
+        complex_value(3.0, -4.0)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("abs<3+4i>")).unwrap(),
+        float_value(53, 5.0)
+    );
+    assert_eq!(*eval_ctx.eval(parse_str("abs<-3>")).unwrap(), int_value(3));
+    assert_eq!(
+        *eval_ctx.eval(parse_str("arg<-1>")).unwrap(),
+        float_value(53, std::f64::consts::PI)
+    );
+    assert_eq!(
+        eval_ctx.eval(parse_str("conj<3+4i>")).unwrap().to_string(),
+        format!("{}-{}i", RFloat::with_val(53, 3), RFloat::with_val(53, 4))
+    );
+
+    match eval_ctx.eval(parse_str("(-1)**0.5")).unwrap() {
+        Value::Number(Number::Complex(c)) => {
+            assert!(c.real().to_f64().abs() < 1e-15);
+            assert_eq!(c.imag().to_f64(), 1.0);
+        }
+        other => panic!("Expected a complex number, got {:?}", other),
+    }
+    match eval_ctx.eval(parse_str("sin<i>")).unwrap() {
+        Value::Number(Number::Complex(c)) => {
+            assert_eq!(c.real().to_f64(), 0.0);
+            assert!((c.imag().to_f64() - 1.0f64.sinh()).abs() < 1e-15);
+        }
+        other => panic!("Expected a complex number, got {:?}", other),
+    }
+}
+
+#[test]
+fn precision() {
+    let mut eval_ctx = EvalContext::new();
+    assert!(eval_ctx.set_precision(0).is_err());
+    eval_ctx.set_precision(200).unwrap();
+
+    match eval_ctx.eval(parse_str("pi")).unwrap() {
+        Value::Number(Number::Float(f)) => {
+            assert_eq!(f.prec(), 200);
+            assert_eq!(*f, RFloat::with_val(200, rug::float::Constant::Pi));
+        }
+        other => panic!("Expected a float, got {:?}", other),
+    }
+    match eval_ctx.eval(parse_str("e")).unwrap() {
+        Value::Number(Number::Float(f)) => assert_eq!(*f, RFloat::with_val(200, 1).exp()),
+        other => panic!("Expected a float, got {:?}", other),
+    }
+    match eval_ctx.eval(parse_str("sqrt<2>")).unwrap() {
+        Value::Number(Number::Float(f)) => assert_eq!(f.prec(), 200),
+        other => panic!("Expected a float, got {:?}", other),
+    }
+    assert_eq!(
+        eval_ctx
+            .eval(parse_str("1/3"))
+            .unwrap()
+            .format(RationalStyle::Decimal, 200),
+        RFloat::with_val(200, Rational::from((1, 3))).to_string()
+    );
+}
+
+#[test]
+fn exponents() {
+    let mut eval_ctx = EvalContext::new();
+    assert_eq!(
+        *eval_ctx.eval(parse_str("1.5e3")).unwrap(),
+        float_value(53, 1500.0)
+    );
+    assert_eq!(
+        *eval_ctx.eval(parse_str("2E-2")).unwrap(),
+        float_value(53, 0.02)
+    );
+    assert_eq!(*eval_ctx.eval(parse_str("1e5h")).unwrap(), int_value(0x1e5));
+}
+
+#[test]
+fn node_source() {
+    for source in &[
+        "1 + 2 * -3 ** 2",
+        "fn f<a, b>{let c = a; c * b}",
+        "f<1, (2 + 3)>",
+        r#"$"echo \"hi\"""#,
+        r#""tab\tnewline\n""#,
+        "# + #3 - ## * #-4",
+        "1/3 + 2.5e-3",
+    ] {
+        let node = parse_str(source);
+        assert_eq!(parse_str(&node.to_string()), node, "{}", source);
+    }
+}
+
+#[test]
+fn workspace() {
+    let mut eval_ctx = EvalContext::new();
+    let source = r#"fn double<x>{x * 2}; let a = (-5); let b = (1/3); let c = (2.5);
+        let d = (1 - 2i); let e = ("quote \" and\nnewline"); let f = (double); let g = (sin);
+        let h = (true); let l = (split<"a,b", ",">)"#;
+    for node in parse_all(source) {
+        eval_ctx.eval(node).unwrap();
+    }
+    let (saved, skipped) = eval_ctx.to_source();
+    assert_eq!(skipped, vec!["l".to_owned()]);
+
+    let mut loaded = EvalContext::new();
+    loaded.eval(parse_str("let unrelated = 1")).unwrap();
+    loaded.load_source(&saved, LoadMode::Replace).unwrap();
+    for name in &["a", "b", "c", "d", "e", "f", "g", "h"] {
+        assert_eq!(
+            loaded.eval(parse_str(name)).unwrap().clone(),
+            *eval_ctx.eval(parse_str(name)).unwrap(),
+            "{}",
+            name
+        );
+    }
+    assert_eq!(*loaded.eval(parse_str("double<4>")).unwrap(), int_value(8));
+    assert!(loaded.eval(parse_str("unrelated")).is_err());
+
+    let mut merged = EvalContext::new();
+    merged.eval(parse_str("let unrelated = 1")).unwrap();
+    merged.load_source(&saved, LoadMode::Merge).unwrap();
+    assert_eq!(*merged.eval(parse_str("unrelated")).unwrap(), int_value(1));
+    assert_eq!(*merged.eval(parse_str("a")).unwrap(), int_value(-5));
+
+    assert!(merged
+        .load_source("let z = (1)\n)", LoadMode::Merge)
+        .is_err());
+    assert!(merged.eval(parse_str("z")).is_err());
+
+    // A variable holding an older version of a function must not redefine it
+    let mut redefined = EvalContext::new();
+    for node in parse_all("fn double<x>{x*2}; let f = double; fn double<x>{x*3}") {
+        redefined.eval(node).unwrap();
+    }
+    let (saved, _) = redefined.to_source();
+    let mut loaded = EvalContext::new();
+    loaded.load_source(&saved, LoadMode::Replace).unwrap();
+    assert_eq!(*loaded.eval(parse_str("double<1>")).unwrap(), int_value(3));
+    assert_eq!(*loaded.eval(parse_str("f<1>")).unwrap(), int_value(2));
+
+    match merged.load_source("let s = ($\"true\")", LoadMode::Merge) {
+        Err(WorkspaceError::Eval(1, EvalError::ShellDisabled(cmd))) => assert_eq!(cmd, "true"),
+        other => panic!("shell call was not refused: {:?}", other),
+    }
+    assert!(merged.eval(parse_str("s")).is_err());
+}
+
+#[test]
+fn diagnostics() {
+    let diagnostic = Diagnostic::new("Unexpected `)`")
+        .label(4, 5, "expected a number")
+        .help("try removing it");
+    assert_eq!(
+        diagnostic.render("1 + )", 1),
+        vec![
+            "error: Unexpected `)`",
+            "  |",
+            "1 | 1 + )",
+            "  |     ^ expected a number",
+            "  = help: try removing it",
+        ]
+    );
+    let at_end = Diagnostic::new("Unexpected end of input").label(9, 9, "expected `)`");
+    assert_eq!(
+        at_end.render("1\n(2 + 3", 1)[2..],
+        ["2 | (2 + 3", "  |       ^ expected `)`"]
+    );
+
+    let input = "1 + )";
+    let mut errors = Vec::new();
+    let lalr_tokens = token::tokenize(input)
+        .unwrap()
+        .into_iter()
+        .map(|token| Ok((token.1.start, token.0, token.1.end)))
+        .collect();
+    let parse_error = match ast::parse(&mut errors, lalr_tokens) {
+        Err(e) => e,
+        Ok(_) => errors.remove(0).error,
+    };
+    let rendered = error::parse_diagnostic(&parse_error, input).render(input, 1);
+    assert_eq!(rendered[0], "error: Unexpected `)`");
+    assert!(rendered[3].starts_with("  |     ^ expected "));
+    assert!(rendered[3].contains("a number"));
+    assert!(!rendered[3].contains('"'));
+}
+
+#[test]
+fn lex_errors() {
+    let errors = token::tokenize("1 @ 2 ? \"a\\q\" ; \"open").unwrap_err();
+    let found: Vec<_> = errors
+        .iter()
+        .map(|e| (e.0.to_string(), e.1.start, e.1.end))
+        .collect();
+    assert_eq!(
+        found,
+        vec![
+            ("Unexpected character '@'".to_owned(), 2, 3),
+            ("Unexpected character '?'".to_owned(), 6, 7),
+            ("Invalid escape sequence `\\q`".to_owned(), 10, 12),
+            ("Unclosed string".to_owned(), 16, 21),
+        ]
+    );
+    assert!(token::tokenize(" \t\n").unwrap().is_empty());
+}
+
+#[test]
+fn spans() {
+    let node = parse_str("1 + -x * (2)");
+    assert_eq!((node.span.start, node.span.end), (0, 12));
+    match &node.node {
+        Infix { lhs, rhs, .. } => {
+            assert_eq!((lhs.span.start, lhs.span.end), (0, 1));
+            assert_eq!((rhs.span.start, rhs.span.end), (4, 12));
+        }
+        other => panic!("expected an infix node, found {:?}", other),
+    }
+
+    let mut eval_ctx = EvalContext::new();
+    let err = eval_ctx.eval(parse_str("2 * (1 + nope)")).unwrap_err();
+    assert_eq!(
+        err.error,
+        EvalError::UnknownIdentifier {
+            name: "nope".to_string(),
+            suggestion: None
+        }
+    );
+    assert_eq!((err.span.start, err.span.end), (9, 13));
+    assert!(err.trace.is_empty());
+
+    eval_ctx.eval(parse_str("fn f<x>{x / 0}")).unwrap();
+    eval_ctx.eval(parse_str("fn g<x>{1 + f<x>}")).unwrap();
+    let err = eval_ctx.eval(parse_str("2 + g<3>")).unwrap_err();
+    assert_eq!(err.error, EvalError::DivisionByZero);
+    assert_eq!((err.span.start, err.span.end), (4, 8));
+    let trace: Vec<_> = err
+        .trace
+        .iter()
+        .map(|frame| (frame.function.as_str(), frame.expr.as_str()))
+        .collect();
+    assert_eq!(trace, vec![("f", "(x / 0)"), ("g", "f<x>")]);
+}
+
+#[test]
+fn suggestions() {
+    let mut eval_ctx = EvalContext::new();
+    let suggestion = |eval_ctx: &mut EvalContext, input: &str| match eval_ctx
+        .eval(parse_str(input))
+        .map_err(|e| e.error)
+    {
+        Err(EvalError::UnknownIdentifier { suggestion, .. })
+        | Err(EvalError::UnknownFunction { suggestion, .. }) => suggestion,
+        other => panic!("expected an unknown name error, found {:?}", other),
+    };
+    assert_eq!(suggestion(&mut eval_ctx, "sni<1>"), Some("sin".to_string()));
+    assert_eq!(
+        suggestion(&mut eval_ctx, "sqr<4>"),
+        Some("sqrt".to_string())
+    );
+    assert_eq!(suggestion(&mut eval_ctx, "xyzzy<1>"), None);
+
+    eval_ctx.eval(parse_str("let radius = 2")).unwrap();
+    eval_ctx.eval(parse_str("fn area<r>{pi * r ** 2}")).unwrap();
+    assert_eq!(
+        suggestion(&mut eval_ctx, "raduis"),
+        Some("radius".to_string())
+    );
+    assert_eq!(
+        suggestion(&mut eval_ctx, "aera<1>"),
+        Some("area".to_string())
+    );
+    // Variables that are not functions are not suggested for calls
+    assert_eq!(suggestion(&mut eval_ctx, "radios<1>"), None);
+
+    // Parameters of the function being evaluated are suggested too
+    eval_ctx.eval(parse_str("fn f<value>{valeu}")).unwrap();
+    assert_eq!(suggestion(&mut eval_ctx, "f<1>"), Some("value".to_string()));
+}
+
+#[test]
+fn long_input_spans() {
+    let padding = "1 + ".repeat(20_000);
+    let input = format!("{}@", padding);
+    let errors = token::tokenize(&input).unwrap_err();
+    assert_eq!(errors.len(), 1);
+    assert_eq!((errors[0].1.start, errors[0].1.end), (80_000, 80_001));
+
+    let source = "let a = 1\r\nlet b = \"é\" + @";
+    let span = token::tokenize(source).unwrap_err()[0].1;
+    assert_eq!(span.start_line_col(source), (1, 14));
+    assert_eq!(span.end_line_col(source), (1, 15));
+    assert_eq!(token::line_col(source, 0), (0, 0));
+    assert_eq!(token::line_col(source, source.len() + 10), (1, 15));
+
+    let diagnostic = Diagnostic::new("Unexpected character '@'").label(span.start, span.end, "");
+    assert_eq!(
+        diagnostic.render(source, 41)[1..],
+        ["   |", "42 | let b = \"é\" + @", "   |               ^"]
+    );
+}
+
+#[test]
+fn snapshot_limits() {
+    let mut eval_ctx = EvalContext::new();
+    eval_ctx.eval(parse_str("fn f<x>{f<x>}")).unwrap();
+    eval_ctx.eval(parse_str("40 + 2")).unwrap();
+
+    let mut snapshot = eval_ctx.snapshot();
+    for input in &[
+        "f<1>",
+        "10**4000000000",
+        "1 << 10**20",
+        "(1/3)**2000000000",
+        "(1/3) << 100000000",
+        "1 << 1e30",
+    ] {
+        assert_eq!(
+            snapshot.eval(parse_str(input)).map_err(|e| e.error),
+            Err(EvalError::LimitExceeded),
+            "{}",
+            input
+        );
+    }
+    assert_eq!(
+        snapshot
+            .eval(parse_str("repeat<\"ab\", 100000>"))
+            .map_err(|e| e.error),
+        Err(EvalError::TooLarge("repeat".to_string()))
+    );
+    assert!(eval_ctx.snapshot().eval(parse_str("sqrt<2>")).is_ok());
+    assert_eq!(*snapshot.eval(parse_str("2**10")).unwrap(), int_value(1024));
+    assert_eq!(
+        *snapshot.eval(parse_str("# + ##")).unwrap(),
+        int_value(1066)
+    );
+    assert_eq!(*snapshot.eval(parse_str("#2")).unwrap(), int_value(42));
+
+    // Results in the snapshot are kept apart from the context's
+    assert_eq!(eval_ctx.results().len(), 2);
+    assert_eq!(*eval_ctx.eval(parse_str("#")).unwrap(), int_value(42));
+    assert!(eval_ctx.eval(parse_str("repeat<\"ab\", 100000>")).is_ok());
+}
+
+#[test]
+fn config_quoted_values() {
+    let mut config = SlideConfig::default();
+    let warnings = config.apply("prompt = \"> \"\nresult_prefix = \"\\\"= \"\n# prompt = x\n");
+    assert!(warnings.is_empty(), "{:?}", warnings);
+    assert_eq!(config.prompts.input, "> ");
+    assert_eq!(config.prompts.result, "\"= ");
+
+    config.apply("error_prefix = \"\n");
+    assert_eq!(config.prompts.error, "\"");
+}
+
+#[test]
+fn config_history_disabled() {
+    for source in &[
+        "history = false\nhistory_file = /tmp/history",
+        "history_file = /tmp/history\nhistory = false",
+    ] {
+        let mut config = SlideConfig::default();
+        assert!(config.apply(source).is_empty());
+        assert_eq!(config.history.path, None, "{}", source);
+    }
+
+    let mut config = SlideConfig::default();
+    config.apply("history = true\nhistory_file = /tmp/history");
+    assert_eq!(config.history.path, Some("/tmp/history".into()));
+}
+
+#[test]
+fn config_invalid_values() {
+    let mut config = SlideConfig::default();
+    let warnings = config.apply("precision = 0\nprecision = lots\nprecision = 128\nlimit = 1\n");
+    assert_eq!(
+        warnings,
+        [
+            "line 1: invalid precision of 0 bits",
+            "line 2: expected a number, found `lots`",
+            "line 4: unknown setting `limit`",
+        ]
+    );
+    assert_eq!(config.precision, Some(128));
+}
+
+#[test]
+fn config_colors() {
+    let mut config = SlideConfig::default();
+    let warnings =
+        config.apply("color.number = \"1;33\"\ncolor.hint = 2m\\x1b[31\ncolor.string = \"\"\n");
+    assert_eq!(
+        warnings,
+        [
+            "line 2: expected SGR codes such as `1;32`, found `2m\\x1b[31`",
+            "line 3: expected SGR codes such as `1;32`, found ``",
+        ]
+    );
+    assert_eq!(config.theme.number, "1;33");
+    assert_eq!(config.theme.hint, Theme::default().hint);
 }